@@ -0,0 +1,121 @@
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+use dirs::config_dir;
+
+/**
+ * 默认账号, 使用原来的 config.json.
+ */
+pub const DEFAULT_PROFILE: &str = "default";
+
+static ACTIVE_PROFILE: OnceLock<String> = OnceLock::new();
+
+fn get_config_root() -> PathBuf {
+    let mut config_root = config_dir().unwrap_or_else(|| PathBuf::from("."));
+    config_root.push("bili_manga_downloader");
+    fs::create_dir_all(&config_root).expect("创建配置文件夹失败");
+    config_root
+}
+
+fn get_current_profile_path() -> PathBuf {
+    get_config_root().join("current_profile")
+}
+
+/**
+ * 账号的配置文件, 默认账号为 config.json, 其他账号保存在 profiles 目录中.
+ */
+pub fn get_profile_config_path(name: &str) -> PathBuf {
+    let config_root = get_config_root();
+    if name == DEFAULT_PROFILE {
+        return config_root.join("config.json");
+    }
+    config_root.join("profiles").join(format!("{}.json", name))
+}
+
+pub fn profile_exists(name: &str) -> bool {
+    name == DEFAULT_PROFILE || get_profile_config_path(name).exists()
+}
+
+/**
+ * 账号名只能包含字母、数字、`-` 与 `_`.
+ */
+pub fn check_profile_name(name: &str) -> Result<(), String> {
+    let is_valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "无效的账号名: {}, 只能包含字母、数字、- 与 _",
+            name
+        ))
+    }
+}
+
+/**
+ * 列出所有账号, 默认账号排在最前.
+ */
+pub fn list_profiles() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(get_config_root().join("profiles"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let path = entry.path();
+                    if path.extension().is_some_and(|ext| ext == "json") {
+                        path.file_stem()?.to_str().map(|name| name.to_string())
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    names
+}
+
+/**
+ * `profile switch` 设置的当前账号.
+ */
+pub fn get_current_profile() -> String {
+    fs::read_to_string(get_current_profile_path())
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| check_profile_name(name).is_ok() && profile_exists(name))
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+pub fn set_current_profile(name: &str) {
+    fs::write(get_current_profile_path(), name).expect("当前账号保存失败");
+}
+
+/**
+ * 设置本次运行使用的账号, 未指定时使用当前账号.
+ */
+pub fn init_active_profile(name: Option<String>) -> Result<(), String> {
+    let name = match name {
+        Some(name) => {
+            check_profile_name(&name)?;
+            if !profile_exists(&name) {
+                return Err(format!(
+                    "账号不存在: {}, 请先使用 profile add {} 添加",
+                    name, name
+                ));
+            }
+            name
+        }
+        None => get_current_profile(),
+    };
+    ACTIVE_PROFILE.set(name).expect("账号已初始化");
+    Ok(())
+}
+
+pub fn active_profile() -> &'static str {
+    ACTIVE_PROFILE
+        .get()
+        .map(|name| name.as_str())
+        .unwrap_or(DEFAULT_PROFILE)
+}
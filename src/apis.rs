@@ -1,56 +1,282 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     io::Write,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
     vec,
 };
 
-use bytes::Bytes;
+use bytes::BytesMut;
 use futures::future::join_all;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use serde::Deserialize;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 use urlencoding::encode;
 use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
 
-use crate::utils::{get_reqwest_headers, get_safe_filename};
+use crate::comicinfo::{build_comic_info, COMIC_INFO_FILE_NAME};
+use crate::config::ImageQuality;
+use crate::cookie::CookieJar;
+use crate::dedupe::{BlockList, PageFilter, SeriesIndex};
+use crate::http::{api_client, image_client};
+use crate::imaging::{validate_page, Page};
+use crate::manifest::{sha256_file, sha256_hex, EpisodeManifest, PageManifest};
+use crate::process::{process_pages, ProcessOptions};
+use crate::transcode::{TranscodeOptions, TranscodeStats};
+use crate::utils::{get_part_path, get_reqwest_headers, get_safe_filename, sync_dir, RateLimiter};
 
 #[derive(Deserialize, Debug)]
 struct UserInfoResponse {
     code: i32,
-    data: UserInfoBody,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    data: UserInfo,
 }
 
-#[derive(Deserialize, Debug)]
-struct UserInfoBody {
+/**
+ * nav 接口返回的登录用户信息.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct UserInfo {
     #[serde(rename = "isLogin")]
     pub is_login: bool,
+    pub mid: u64,
+    pub uname: String,
+    /// 大会员状态, 1 为有效.
+    #[serde(rename = "vipStatus")]
+    pub vip_status: i32,
+    /// 大会员类型, 1 为月度大会员, 2 为年度大会员.
+    #[serde(rename = "vipType")]
+    pub vip_type: i32,
+    /// 大会员到期时间, 单位为毫秒.
+    #[serde(rename = "vipDueDate")]
+    pub vip_due_date: u64,
+}
+
+impl UserInfo {
+    pub fn is_vip(&self) -> bool {
+        self.vip_status == 1
+    }
 }
 
-pub async fn get_userinfo(cookie: &str) -> bool {
+/**
+ * 获取登录用户信息失败的原因.
+ */
+#[derive(Debug)]
+pub enum UserInfoError {
+    /// Cookie 无效或已过期, 需要重新登录.
+    NotLoggedIn,
+    /// 网络错误或接口异常, 与登录状态无关.
+    Request(Box<dyn std::error::Error>),
+}
+
+impl fmt::Display for UserInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserInfoError::NotLoggedIn => f.write_str("Cookie 无效或已过期"),
+            UserInfoError::Request(e) => write!(f, "用户信息获取失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UserInfoError {}
+
+/**
+ * 获取登录用户信息, 区分未登录与请求失败.
+ */
+pub async fn get_userinfo(cookie: &CookieJar) -> Result<UserInfo, UserInfoError> {
     let referer_url = "https://manga.bilibili.com/";
     let headers = get_reqwest_headers(referer_url, cookie);
 
-    let client = reqwest::Client::new();
+    let client = api_client();
     let res = client
         .get("https://api.bilibili.com/x/web-interface/nav")
         .headers(headers)
         .send()
-        .await;
+        .await
+        .map_err(|e| UserInfoError::Request(e.into()))?;
+    cookie.store_response(res.headers());
+
+    if res.status() != reqwest::StatusCode::OK {
+        return Err(UserInfoError::Request(
+            format!("请求失败: {:?}", res.status()).into(),
+        ));
+    }
+    let resp_body = res
+        .json::<UserInfoResponse>()
+        .await
+        .map_err(|e| UserInfoError::Request(e.into()))?;
+
+    // 未登录时返回 -101 且 isLogin 为 false.
+    if resp_body.code == -101 || (resp_body.code == 0 && !resp_body.data.is_login) {
+        return Err(UserInfoError::NotLoggedIn);
+    }
+    if resp_body.code != 0 {
+        return Err(UserInfoError::Request(resp_body.message.into()));
+    }
+
+    Ok(resp_body.data)
+}
+
+#[derive(Deserialize, Debug)]
+struct PassportResponse<T> {
+    code: i32,
+    message: String,
+    data: Option<T>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LoginQrcode {
+    pub url: String,
+    pub qrcode_key: String,
+}
+
+/**
+ * 申请扫码登录的二维码.
+ */
+pub async fn generate_login_qrcode() -> Result<LoginQrcode, Box<dyn std::error::Error>> {
+    let base_url = "https://passport.bilibili.com/x/passport-login/web/qrcode/generate";
+    let headers = get_reqwest_headers("https://passport.bilibili.com/login", &CookieJar::default());
+
+    let client = api_client();
+    let res = client.get(base_url).headers(headers).send().await?;
+
+    if res.status() != reqwest::StatusCode::OK {
+        println!("二维码申请失败: {:?}", res.status());
+        return Err("二维码申请失败".into());
+    }
+    let resp_body = res.json::<PassportResponse<LoginQrcode>>().await?;
+
+    match resp_body.data {
+        Some(data) if resp_body.code == 0 => Ok(data),
+        _ => Err(resp_body.message.into()),
+    }
+}
+
+/**
+ * 扫码登录后跳转地址中携带的 Cookie.
+ */
+const LOGIN_COOKIE_NAMES: [&str; 4] = ["DedeUserID", "DedeUserID__ckMd5", "SESSDATA", "bili_jct"];
+
+#[derive(Deserialize, Debug)]
+struct QrcodePollBody {
+    code: i32,
+    #[serde(default)]
+    url: String,
+}
+
+/**
+ * 扫码登录的状态.
+ */
+#[derive(Debug)]
+pub enum QrcodeLoginStatus {
+    /// 未扫码.
+    Waiting,
+    /// 已扫码, 等待在手机上确认.
+    Scanned,
+    /// 二维码已失效.
+    Expired,
+    /// 登录成功, 返回服务端设置的 Cookie.
+    Confirmed(CookieJar),
+}
+
+/**
+ * 查询扫码登录的状态.
+ */
+pub async fn poll_login_qrcode(
+    qrcode_key: &str,
+) -> Result<QrcodeLoginStatus, Box<dyn std::error::Error>> {
+    let base_url = format!(
+        "https://passport.bilibili.com/x/passport-login/web/qrcode/poll?qrcode_key={}",
+        encode(qrcode_key)
+    );
+    let headers = get_reqwest_headers("https://passport.bilibili.com/login", &CookieJar::default());
+
+    let client = api_client();
+    let res = client.get(base_url).headers(headers).send().await?;
 
-    if res.is_err() {
-        return false;
+    if res.status() != reqwest::StatusCode::OK {
+        println!("登录状态查询失败: {:?}", res.status());
+        return Err("登录状态查询失败".into());
     }
+    let cookies = CookieJar::default();
+    cookies.store_response(res.headers());
+    let resp_body = res.json::<PassportResponse<QrcodePollBody>>().await?;
+
+    let Some(data) = resp_body.data.filter(|_| resp_body.code == 0) else {
+        return Err(resp_body.message.into());
+    };
 
-    let res = res.unwrap();
+    let status = match data.code {
+        0 => {
+            // Set-Cookie 缺失时从跳转地址的参数中读取.
+            if cookies.get("SESSDATA").is_none() {
+                let query = data.url.split_once('?').map(|(_, query)| query);
+                let from_query: BTreeMap<String, String> = query
+                    .unwrap_or_default()
+                    .split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .filter(|(name, _)| LOGIN_COOKIE_NAMES.contains(name))
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect();
+                QrcodeLoginStatus::Confirmed(CookieJar::from_map(from_query))
+            } else {
+                QrcodeLoginStatus::Confirmed(cookies)
+            }
+        }
+        86090 => QrcodeLoginStatus::Scanned,
+        86038 => QrcodeLoginStatus::Expired,
+        _ => QrcodeLoginStatus::Waiting,
+    };
 
-    let resp_body = res.json::<UserInfoResponse>().await.unwrap();
+    Ok(status)
+}
+
+#[derive(Deserialize, Debug)]
+struct LogoutResponse {
+    code: i32,
+    #[serde(default)]
+    message: String,
+}
+
+/**
+ * 退出登录, 使当前 Cookie 失效. 该接口需要 CSRF token.
+ */
+pub async fn logout(cookie: &CookieJar) -> Result<(), Box<dyn std::error::Error>> {
+    let base_url = "https://passport.bilibili.com/login/exit/v2";
+    let mut headers = get_reqwest_headers("https://manga.bilibili.com/", cookie);
+    headers.insert(
+        reqwest::header::CONTENT_TYPE,
+        "application/x-www-form-urlencoded".parse().unwrap(),
+    );
+
+    let mut request_body = HashMap::new();
+    request_body.insert("biliCSRF", cookie.csrf()?);
+
+    let client = api_client();
+    let res = client
+        .post(base_url)
+        .form(&request_body)
+        .headers(headers)
+        .send()
+        .await?;
+    cookie.store_response(res.headers());
+
+    if res.status() != reqwest::StatusCode::OK {
+        println!("退出登录失败: {:?}", res.status());
+        return Err("退出登录失败".into());
+    }
+    let resp_body = res.json::<LogoutResponse>().await?;
 
     if resp_body.code != 0 {
-        return false;
+        return Err(resp_body.message.into());
     }
 
-    resp_body.data.is_login
+    Ok(())
 }
 
 #[derive(Deserialize, Debug)]
@@ -80,16 +306,16 @@ pub async fn search_manga(
     let base_url = "https://manga.bilibili.com/twirp/comic.v1.Comic/Search?device=pc&platform=web";
     let referer_url = format!(
         "https://manga.bilibili.com/search?from=manga_homepage&keyword={}",
-        encode(&name).to_string()
+        encode(&name)
     );
-    let headers = get_reqwest_headers(&referer_url, "");
+    let headers = get_reqwest_headers(&referer_url, &CookieJar::default());
 
     let mut request_body = HashMap::new();
     request_body.insert("key_word", name);
     request_body.insert("page_num", "1".to_string());
     request_body.insert("page_size", "3".to_string());
 
-    let client = reqwest::Client::new();
+    let client = api_client();
     let res = client
         .post(base_url)
         .json(&request_body)
@@ -125,7 +351,7 @@ pub struct MangaDetailResponse {
     pub ep_list: Vec<Episode>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Episode {
     pub id: u32,
     pub title: String,
@@ -135,7 +361,7 @@ pub struct Episode {
 
 pub async fn get_manga_detail(
     manga_id: &u32,
-    cookie: &str,
+    cookie: &CookieJar,
 ) -> Result<MangaDetailResponse, Box<dyn std::error::Error>> {
     let base_url =
         "https://manga.bilibili.com/twirp/comic.v1.Comic/ComicDetail?device=pc&platform=web";
@@ -148,14 +374,14 @@ pub async fn get_manga_detail(
     let mut request_body = HashMap::new();
     request_body.insert("comic_id", manga_id.to_string());
 
-    let client = reqwest::Client::new();
+    let client = api_client();
     let res = client
         .post(base_url)
         .json(&request_body)
         .headers(headers)
         .send()
-        .await
-        .unwrap();
+        .await?;
+    cookie.store_response(res.headers());
 
     if res.status() != reqwest::StatusCode::OK {
         println!("请求失败: {:?}", res.status());
@@ -171,6 +397,269 @@ pub async fn get_manga_detail(
     Ok(resp_body.data)
 }
 
+/**
+ * 调用漫画的 twirp 接口, 如 `user.v1.User/GetWallet`.
+ */
+async fn post_twirp<B: Serialize, T: DeserializeOwned>(
+    method: &str,
+    referer_url: &str,
+    request_body: &B,
+    cookie: &CookieJar,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let base_url = format!(
+        "https://manga.bilibili.com/twirp/{}?device=pc&platform=web",
+        method
+    );
+    let headers = get_reqwest_headers(referer_url, cookie);
+
+    let client = api_client();
+    let res = client
+        .post(base_url)
+        .json(request_body)
+        .headers(headers)
+        .send()
+        .await?;
+    cookie.store_response(res.headers());
+
+    if res.status() != reqwest::StatusCode::OK {
+        return Err(format!("{} 请求失败: {:?}", method, res.status()).into());
+    }
+    let resp_body = res.json::<CommonResponse<T>>().await?;
+
+    if resp_body.code != 0 {
+        return Err(format!("{} 请求失败: {}", method, resp_body.msg).into());
+    }
+
+    Ok(resp_body.data)
+}
+
+/**
+ * 章节压缩文件的文件名, 如 `[1]第一话.zip`.
+ */
+pub fn get_episode_filename(episode: &Episode) -> String {
+    format!("[{}]{}.zip", episode.ord, get_safe_filename(&episode.title))
+}
+
+/**
+ * 未解锁章节试看页面的文件名, 如 `[preview][12]第十二话.zip`.
+ */
+pub fn get_preview_filename(episode: &Episode) -> String {
+    format!("[preview]{}", get_episode_filename(episode))
+}
+
+/**
+ * 书架中追漫的漫画.
+ */
+#[derive(Deserialize, Debug)]
+pub struct FavoriteComic {
+    pub comic_id: u32,
+    pub title: String,
+}
+
+/**
+ * 获取书架中追漫的所有漫画.
+ */
+pub async fn list_favorites(
+    cookie: &CookieJar,
+) -> Result<Vec<FavoriteComic>, Box<dyn std::error::Error>> {
+    let page_size = 50;
+    let mut comics = vec![];
+    for page_num in 1.. {
+        let request_body = serde_json::json!({
+            "page_num": page_num,
+            "page_size": page_size,
+            "order": 1,
+            "wait_free": 0,
+        });
+        let page: Vec<FavoriteComic> = post_twirp(
+            "bookshelf.v1.Bookshelf/ListFavorite",
+            "https://manga.bilibili.com/account-center/bookshelf",
+            &request_body,
+            cookie,
+        )
+        .await?;
+        let is_last_page = page.len() < page_size;
+        comics.extend(page);
+        if is_last_page {
+            break;
+        }
+    }
+    Ok(comics)
+}
+
+/**
+ * 购买过章节的漫画.
+ */
+#[derive(Deserialize, Debug)]
+pub struct BoughtComic {
+    pub comic_id: u32,
+    pub title: String,
+}
+
+/**
+ * 获取账号购买过章节的所有漫画.
+ */
+pub async fn list_bought_comics(
+    cookie: &CookieJar,
+) -> Result<Vec<BoughtComic>, Box<dyn std::error::Error>> {
+    let page_size = 50;
+    let mut comics = vec![];
+    for page_num in 1.. {
+        let request_body = serde_json::json!({
+            "page_num": page_num,
+            "page_size": page_size,
+        });
+        let page: Vec<BoughtComic> = post_twirp(
+            "user.v1.User/GetBoughtComics",
+            "https://manga.bilibili.com/account-center/purchased",
+            &request_body,
+            cookie,
+        )
+        .await?;
+        let is_last_page = page.len() < page_size;
+        comics.extend(page);
+        if is_last_page {
+            break;
+        }
+    }
+    Ok(comics)
+}
+
+#[derive(Deserialize, Debug)]
+struct BoughtEpisode {
+    id: u32,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct BoughtEpisodePage {
+    ep_list: Vec<BoughtEpisode>,
+}
+
+/**
+ * 获取漫画中通过购买接口购买过的章节 ID.
+ *
+ * 不包括通过漫读券、等就免费、限免活动或大会员解锁的章节.
+ */
+pub async fn list_bought_episode_ids(
+    comic_id: u32,
+    cookie: &CookieJar,
+) -> Result<HashSet<u32>, Box<dyn std::error::Error>> {
+    let page_size = 100;
+    let mut episode_ids = HashSet::new();
+    for page_num in 1.. {
+        let request_body = serde_json::json!({
+            "comic_id": comic_id,
+            "page_num": page_num,
+            "page_size": page_size,
+        });
+        let page: BoughtEpisodePage = post_twirp(
+            "comic.v1.Comic/GetBoughtEpisode",
+            "https://manga.bilibili.com/account-center/purchased",
+            &request_body,
+            cookie,
+        )
+        .await?;
+        let is_last_page = page.ep_list.len() < page_size;
+        episode_ids.extend(page.ep_list.into_iter().map(|episode| episode.id));
+        if is_last_page {
+            break;
+        }
+    }
+    Ok(episode_ids)
+}
+
+/**
+ * 漫画钱包.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Wallet {
+    /// 漫读券数量.
+    pub remain_coupon: i64,
+    /// 漫币余额.
+    pub remain_gold: i64,
+}
+
+pub async fn get_wallet(cookie: &CookieJar) -> Result<Wallet, Box<dyn std::error::Error>> {
+    let request_body: HashMap<&str, String> = HashMap::new();
+    post_twirp(
+        "user.v1.User/GetWallet",
+        "https://manga.bilibili.com/account-center",
+        &request_body,
+        cookie,
+    )
+    .await
+}
+
+/**
+ * 漫画的大会员信息.
+ */
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct VipInfo {
+    /// 大会员状态, 1 为有效.
+    pub vip_status: i32,
+    /// 大会员类型, 1 为月度大会员, 2 为年度大会员.
+    pub vip_type: i32,
+    /// 大会员到期时间, 单位为毫秒.
+    pub vip_due_date: u64,
+}
+
+impl VipInfo {
+    pub fn is_vip(&self) -> bool {
+        self.vip_status == 1
+    }
+}
+
+pub async fn get_vip_info(cookie: &CookieJar) -> Result<VipInfo, Box<dyn std::error::Error>> {
+    let request_body: HashMap<&str, String> = HashMap::new();
+    post_twirp(
+        "user.v1.User/GetVipInfo",
+        "https://manga.bilibili.com/account-center",
+        &request_body,
+        cookie,
+    )
+    .await
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Coupon {
+    pub remain_amount: i64,
+    /// 过期时间, 如 `2024-07-01 00:00:00`.
+    pub expire_time: String,
+    pub type_name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CouponList {
+    /// 未过期的漫读券总数.
+    pub total_remain_amount: i64,
+    pub user_coupons: Vec<Coupon>,
+}
+
+/**
+ * 获取未过期的漫读券.
+ */
+pub async fn get_coupons(cookie: &CookieJar) -> Result<CouponList, Box<dyn std::error::Error>> {
+    let request_body = serde_json::json!({
+        "not_expired": true,
+        "page_num": 1,
+        "page_size": 100,
+        "tab_type": 1,
+        "type": 0,
+    });
+    post_twirp(
+        "user.v1.User/GetCoupons",
+        "https://manga.bilibili.com/account-center/ticket",
+        &request_body,
+        cookie,
+    )
+    .await
+}
+
 #[derive(Deserialize, Debug)]
 struct ImageIndexResponse {
     images: Vec<ImageData>,
@@ -179,6 +668,22 @@ struct ImageIndexResponse {
 #[derive(Deserialize, Debug)]
 struct ImageData {
     path: String,
+    /// 图片宽度.
+    #[serde(default)]
+    x: u32,
+    /// 图片高度.
+    #[serde(default)]
+    y: u32,
+}
+
+/**
+ * 带 token 的图片地址及图片索引中记录的原图宽高.
+ */
+#[derive(Debug, Clone)]
+struct ImageUrl {
+    url: String,
+    width: u32,
+    height: u32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -190,8 +695,9 @@ struct ImageTokenResponse {
 async fn get_image_urls(
     manga_id: u32,
     episode_id: u32,
-    cookie: &str,
-) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    cookie: &CookieJar,
+    quality: &ImageQuality,
+) -> Result<Vec<ImageUrl>, Box<dyn std::error::Error>> {
     // 获取图片 url.
     let base_url =
         "https://manga.bilibili.com/twirp/comic.v1.Comic/GetImageIndex?device=pc&platform=web";
@@ -204,13 +710,14 @@ async fn get_image_urls(
     let mut request_body = HashMap::new();
     request_body.insert("ep_id", episode_id.to_string());
 
-    let client = reqwest::Client::new();
+    let client = api_client();
     let res = client
         .post(base_url)
         .json(&request_body)
         .headers(headers.clone())
         .send()
         .await?;
+    cookie.store_response(res.headers());
 
     if res.status() != reqwest::StatusCode::OK {
         println!("ImageIndex 请求失败: {:?}", res.status());
@@ -223,11 +730,10 @@ async fn get_image_urls(
         return Err(resp_body.msg.into());
     }
 
-    let image_urls: Vec<String> = resp_body
-        .data
-        .images
+    let images = resp_body.data.images;
+    let image_urls: Vec<String> = images
         .iter()
-        .map(|image| image.path.clone())
+        .map(|image| quality.apply(&image.path, image.x))
         .collect();
 
     // 获取图片 token.
@@ -243,6 +749,7 @@ async fn get_image_urls(
         .headers(headers)
         .send()
         .await?;
+    cookie.store_response(res.headers());
     if res.status() != reqwest::StatusCode::OK {
         println!("ImageToken 请求失败: {:?}", res.status());
         return Err("ImageToken 请求失败".into());
@@ -255,71 +762,352 @@ async fn get_image_urls(
     let resp_body = res
         .json::<CommonResponse<Vec<ImageTokenResponse>>>()
         .await?;
-    let image_urls: Vec<String> = resp_body
+    let image_urls: Vec<ImageUrl> = resp_body
         .data
         .iter()
-        .map(|item| format!("{}?token={}", item.url, item.token))
+        .zip(images.iter())
+        .map(|(item, image)| ImageUrl {
+            url: format!("{}?token={}", item.url, item.token),
+            width: image.x,
+            height: image.y,
+        })
         .collect();
 
     Ok(image_urls)
 }
 
-async fn download_image(image_urls: Vec<String>, pb: &ProgressBar) -> Vec<Bytes> {
-    let mut image_bytes = vec![];
-    for url in image_urls {
-        let resp = reqwest::get(&url).await;
-        if let Err(_) = resp {
-            return vec![];
+/**
+ * 下载单张图片, 超过 `stall_timeout` 没有收到数据时视为卡住.
+ *
+ * 状态码、Content-Length 或图片内容校验不通过时返回错误, 由调用方重试.
+ */
+async fn fetch_image(
+    image_url: &ImageUrl,
+    options: &DownloadOptions,
+    total_pb: &ProgressBar,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<Page, String> {
+    let stall_timeout = options.stall_timeout;
+    let mut resp = tokio::time::timeout(stall_timeout, image_client().get(&image_url.url).send())
+        .await
+        .map_err(|_| "连接超时".to_string())?
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("请求失败: {}", resp.status()));
+    }
+    let content_length = resp.content_length();
+    let mut buf = BytesMut::new();
+    loop {
+        let chunk = tokio::time::timeout(stall_timeout, resp.chunk())
+            .await
+            .map_err(|_| format!("{} 秒内没有收到数据", stall_timeout.as_secs()))?
+            .map_err(|e| e.to_string())?;
+        let Some(chunk) = chunk else {
+            break;
+        };
+        if let Some(rate_limiter) = rate_limiter {
+            rate_limiter.acquire(chunk.len()).await;
         }
-        let bytes = resp.unwrap().bytes().await;
-        if let Err(_) = bytes {
-            return vec![];
+        total_pb.inc(chunk.len() as u64);
+        buf.extend_from_slice(&chunk);
+    }
+
+    if let Some(content_length) = content_length {
+        if buf.len() as u64 != content_length {
+            return Err(format!(
+                "数据不完整: 收到 {} 字节, 应为 {} 字节",
+                buf.len(),
+                content_length
+            ));
         }
-        image_bytes.push(bytes.unwrap());
+    }
+
+    let bytes = buf.freeze();
+    let full_decode = options.decode_check;
+    let expected_size = Some((image_url.width, image_url.height));
+    tokio::task::spawn_blocking(move || validate_page(bytes, expected_size, full_decode))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+async fn download_image(
+    image_urls: Vec<ImageUrl>,
+    pb: &ProgressBar,
+    total_pb: &ProgressBar,
+    rate_limiter: Option<&RateLimiter>,
+    options: &DownloadOptions,
+) -> Result<Vec<Page>, String> {
+    let mut pages = vec![];
+    for (index, image_url) in image_urls.iter().enumerate() {
+        let mut attempt = 0;
+        let page = loop {
+            match fetch_image(image_url, options, total_pb, rate_limiter).await {
+                Ok(page) => break page,
+                Err(e) if attempt < options.retries => {
+                    attempt += 1;
+                    pb.println(format!(
+                        "第 {} 页下载失败({}), 第 {} 次重试",
+                        index + 1,
+                        e,
+                        attempt
+                    ));
+                }
+                Err(e) => return Err(format!("第 {} 页下载失败: {}", index + 1, e)),
+            }
+        };
+        pages.push(page);
         pb.inc(1);
     }
-    image_bytes
+    Ok(pages)
 }
 
+/**
+ * 先写入同目录下的 `.part` 临时文件, 同步到磁盘后再重命名, 避免留下不完整的压缩文件.
+ *
+ * 压缩文件写入完成后在旁边生成校验清单.
+ */
 async fn create_zip(
-    image_bytes: Vec<Bytes>,
+    pages: Vec<Page>,
     dest_path: &Path,
+    manga_id: u32,
+    episode: &Episode,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let file = std::fs::File::create(dest_path).unwrap();
+    let page_names: Vec<String> = pages
+        .iter()
+        .enumerate()
+        .map(|(index, page)| format!("{}.{}", index, page.format.extension()))
+        .collect();
+    let series = dest_path
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().to_string());
+    let comic_info = build_comic_info(episode, series.as_deref(), &pages);
+
+    let part_path = get_part_path(dest_path);
+    if let Err(e) = write_zip(&page_names, &pages, &comic_info, &part_path) {
+        let _ = std::fs::remove_file(&part_path);
+        return Err(e);
+    }
+    let archive_sha256 = sha256_file(&part_path)?;
+    std::fs::rename(&part_path, dest_path)?;
+    sync_dir(dest_path);
+
+    let manifest = EpisodeManifest {
+        manga_id,
+        episode: episode.clone(),
+        archive_sha256,
+        pages: page_names
+            .into_iter()
+            .zip(pages.iter())
+            .map(|(name, page)| PageManifest {
+                name,
+                size: page.bytes.len() as u64,
+                sha256: sha256_hex(&page.bytes),
+                width: page.width,
+                height: page.height,
+                double_page: page.double_page,
+            })
+            .collect(),
+    };
+    manifest.save(&EpisodeManifest::path_for(dest_path))?;
+    Ok(())
+}
+
+fn write_zip(
+    page_names: &[String],
+    pages: &[Page],
+    comic_info: &str,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::create(path)?;
     let mut zip = ZipWriter::new(file);
     let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
-    for (index, bytes) in image_bytes.iter().enumerate() {
-        let file_name = format!("{}.jpg", index);
-        zip.start_file(file_name, options).unwrap();
-        zip.write_all(&bytes).unwrap();
+    for (file_name, page) in page_names.iter().zip(pages) {
+        zip.start_file(file_name.as_str(), options)?;
+        zip.write_all(&page.bytes)?;
     }
-    zip.finish()?;
+    zip.start_file(COMIC_INFO_FILE_NAME, options)?;
+    zip.write_all(comic_info.as_bytes())?;
+    let file = zip.finish()?;
+    file.sync_all()?;
     Ok(())
 }
 
+/**
+ * 下载任务的可选参数.
+ */
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// 所有图片下载共享的带宽上限, 单位为字节/秒.
+    pub max_rate: Option<u64>,
+    /// 单张图片持续没有收到数据的最长时间.
+    pub stall_timeout: Duration,
+    /// 单张图片的最大重试次数.
+    pub retries: u32,
+    /// 是否完整解码每张图片以校验数据.
+    pub decode_check: bool,
+    /// 从 CDN 请求的图片尺寸与格式.
+    pub image_quality: ImageQuality,
+    /// 屏蔽的图片.
+    pub block_list: BlockList,
+    /// 是否删除同一部漫画中已经下载过的完全相同的页面.
+    pub drop_duplicates: bool,
+    /// 写入压缩文件前对页面的处理.
+    pub process: ProcessOptions,
+    /// 写入压缩文件前的转码设置.
+    pub transcode: Option<TranscodeOptions>,
+    /// 未解锁章节的处理方式.
+    pub locked_policy: LockedPolicy,
+}
+
+/**
+ * 未解锁章节的处理方式.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LockedPolicy {
+    /// 跳过未解锁的章节.
+    #[default]
+    Skip,
+    /// 选择了未解锁的章节时不下载任何章节.
+    Fail,
+    /// 下载试看页面, 保存为带 `[preview]` 前缀的压缩文件.
+    Preview,
+}
+
+/**
+ * 下载结果汇总.
+ */
+#[derive(Debug, Default)]
+pub struct DownloadReport {
+    /// 下载完成的文件名.
+    pub completed: Vec<String>,
+    /// 下载失败的提示信息.
+    pub failed: Vec<String>,
+    /// 下载失败的章节.
+    pub failed_episodes: Vec<Episode>,
+    /// 所有页面都被删除而跳过的章节.
+    pub skipped: Vec<String>,
+    /// 因取消而未下载完成的章节.
+    pub cancelled: Vec<Episode>,
+    /// 未解锁章节的处理结果.
+    pub locked: Vec<String>,
+    /// 转码统计, 未开启转码时为空.
+    pub transcode_summary: Option<String>,
+}
+
+enum TaskOutcome {
+    Completed(String),
+    Failed(Episode, String),
+    Skipped(String),
+    Cancelled(Episode),
+}
+
+/**
+ * 未解锁章节的处理方式为 `Fail` 时, 选择了未解锁的章节则返回错误.
+ */
+fn check_locked_episodes(
+    episodes: &[Episode],
+    locked_policy: LockedPolicy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if locked_policy != LockedPolicy::Fail {
+        return Ok(());
+    }
+    let locked: Vec<String> = episodes
+        .iter()
+        .filter(|episode| episode.is_locked)
+        .map(get_episode_filename)
+        .collect();
+    if locked.is_empty() {
+        return Ok(());
+    }
+    Err(format!("以下章节未解锁, 已取消下载:\n{}", locked.join("\n")).into())
+}
+
 pub async fn do_download_tasks(
     manga_id: u32,
     episodes: Vec<Episode>,
-    cookie: &str,
+    cookie: &CookieJar,
     dest_path: &PathBuf,
-) -> Vec<String> {
+    options: &DownloadOptions,
+    cancel_token: &CancellationToken,
+) -> Result<DownloadReport, Box<dyn std::error::Error>> {
+    check_locked_episodes(&episodes, options.locked_policy)?;
+
     let semaphore = Arc::new(tokio::sync::Semaphore::new(6));
     let mut handles = vec![];
     let multi_progress = MultiProgress::new();
-    let mut failed_message = vec![];
+    let mut report = DownloadReport::default();
+
+    let rate_limiter = options
+        .max_rate
+        .map(|rate| Arc::new(RateLimiter::new(rate)));
+    // 汇总所有章节的下载速度.
+    let total_pb = multi_progress.add(ProgressBar::new_spinner());
+    total_pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} 已下载 {binary_bytes} ({binary_bytes_per_sec}) {msg}")
+            .expect("Failed to set bar template"),
+    );
+    total_pb.set_message(match &rate_limiter {
+        Some(rate_limiter) => format!("限速 {}/s", HumanBytes(rate_limiter.bytes_per_sec())),
+        None => "不限速".to_string(),
+    });
+    total_pb.enable_steady_tick(Duration::from_millis(100));
+    let transcode_stats = Arc::new(TranscodeStats::default());
+    let series_index = options
+        .drop_duplicates
+        .then(|| Arc::new(SeriesIndex::load(dest_path)));
+    let page_filter = Arc::new(PageFilter::new(&options.block_list, series_index.clone()));
 
     for episode in episodes {
-        let filename = format!("[{}]{}.zip", episode.ord, get_safe_filename(&episode.title));
-        let image_urls = get_image_urls(manga_id, episode.id, cookie).await;
+        if cancel_token.is_cancelled() {
+            report.cancelled.push(episode);
+            continue;
+        }
+
+        let filename = if episode.is_locked {
+            match options.locked_policy {
+                LockedPolicy::Skip => {
+                    report
+                        .locked
+                        .push(format!("{} 未解锁, 已跳过", get_episode_filename(&episode)));
+                    continue;
+                }
+                LockedPolicy::Fail => unreachable!("未解锁的章节已在下载前检查"),
+                LockedPolicy::Preview => {
+                    let filename = get_preview_filename(&episode);
+                    report
+                        .locked
+                        .push(format!("{} 未解锁, 只下载试看页面", &filename));
+                    filename
+                }
+            }
+        } else {
+            get_episode_filename(&episode)
+        };
+        let image_urls = tokio::select! {
+            image_urls = get_image_urls(manga_id, episode.id, cookie, &options.image_quality) => image_urls,
+            _ = cancel_token.cancelled() => {
+                report.cancelled.push(episode);
+                continue;
+            }
+        };
         if let Err(e) = image_urls {
             let error_msg = format!("{} 图片地址获取失败: {}", &filename, e);
-            failed_message.push(error_msg);
+            report.failed.push(error_msg);
+            report.failed_episodes.push(episode);
             continue;
         }
 
         let image_urls = image_urls.unwrap();
         let dest_path = PathBuf::from(dest_path).join(&filename);
-        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let permit = tokio::select! {
+            permit = semaphore.clone().acquire_owned() => permit.unwrap(),
+            _ = cancel_token.cancelled() => {
+                report.cancelled.push(episode);
+                continue;
+            }
+        };
         let pb = multi_progress.add(ProgressBar::new(image_urls.len() as u64));
         pb.set_style(
             ProgressStyle::default_bar()
@@ -331,34 +1119,136 @@ pub async fn do_download_tasks(
         );
         pb.set_message(format!("{} 下载中", filename));
 
+        let total_pb = total_pb.clone();
+        let rate_limiter = rate_limiter.clone();
+        let options = options.clone();
+        let cancel_token = cancel_token.clone();
+        let transcode_stats = transcode_stats.clone();
+        let page_filter = page_filter.clone();
         let handle = tokio::spawn(async move {
-            let result = download_image(image_urls, &pb).await;
+            // 取消时丢弃未下载完的章节, 已经开始写入的压缩文件会继续写完.
+            let result = tokio::select! {
+                result = download_image(
+                    image_urls,
+                    &pb,
+                    &total_pb,
+                    rate_limiter.as_deref(),
+                    &options,
+                ) => result,
+                _ = cancel_token.cancelled() => {
+                    pb.finish_with_message(format!("{} 已取消", filename));
+                    return TaskOutcome::Cancelled(episode);
+                }
+            };
 
             drop(permit);
 
-            if result.is_empty() {
-                let error_msg = format!("{} 下载失败", filename);
-                pb.finish_with_message(error_msg.clone());
-                return Err(error_msg);
-            }
-            if let Err(e) = create_zip(result, &dest_path).await {
+            let result = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    let error_msg = format!("{} 下载失败: {}", filename, e);
+                    pb.finish_with_message(error_msg.clone());
+                    return TaskOutcome::Failed(episode, error_msg);
+                }
+            };
+            // 章节没有写入压缩文件时删除其页面记录.
+            let release = || {
+                if let Some(series_index) = &page_filter.series {
+                    series_index.remove(episode.id);
+                }
+            };
+            let result = if page_filter.is_empty() {
+                result
+            } else {
+                let page_count = result.len();
+                let filter = page_filter.clone();
+                let episode_id = episode.id;
+                let (kept, dropped) =
+                    tokio::task::spawn_blocking(move || filter.filter(episode_id, result))
+                        .await
+                        .unwrap();
+                for message in dropped {
+                    pb.println(format!("{} {}, 已删除", filename, message));
+                }
+                // 所有页面都被屏蔽或重复时跳过该章节, 不写入空的压缩文件.
+                if kept.is_empty() && page_count > 0 {
+                    release();
+                    let message = format!("{} 所有页面都已删除, 未写入压缩文件", filename);
+                    pb.finish_with_message(message.clone());
+                    return TaskOutcome::Skipped(message);
+                }
+                kept
+            };
+            let result = if options.process.is_empty() && options.transcode.is_none() {
+                result
+            } else {
+                let message = if options.process.is_empty() {
+                    "转码中"
+                } else {
+                    "处理中"
+                };
+                pb.set_message(format!("{} {}", filename, message));
+                let process = options.process.clone();
+                let transcode = options.transcode.clone();
+                let processed = tokio::task::spawn_blocking(move || {
+                    process_pages(
+                        result,
+                        &process,
+                        transcode
+                            .as_ref()
+                            .map(|transcode| (transcode, transcode_stats.as_ref())),
+                    )
+                })
+                .await
+                .unwrap();
+                match processed {
+                    Ok(result) => result,
+                    Err(e) => {
+                        release();
+                        let error_msg = format!("{} 图片处理失败: {}", filename, e);
+                        pb.finish_with_message(error_msg.clone());
+                        return TaskOutcome::Failed(episode, error_msg);
+                    }
+                }
+            };
+            if let Err(e) = create_zip(result, &dest_path, manga_id, &episode).await {
+                release();
                 let error_msg = format!("{} 创建压缩文件失败: {}", filename, e);
                 pb.finish_with_message(error_msg.clone());
-                return Err(error_msg);
+                return TaskOutcome::Failed(episode, error_msg);
             }
 
             pb.finish_with_message(format!("{} 下载完成", filename));
-            Ok(())
+            TaskOutcome::Completed(filename)
         });
         handles.push(handle);
     }
 
     let results = join_all(handles).await;
     for result in results {
-        if let Err(e) = result.unwrap() {
-            failed_message.push(e);
+        match result.unwrap() {
+            TaskOutcome::Completed(filename) => report.completed.push(filename),
+            TaskOutcome::Failed(episode, e) => {
+                report.failed.push(e);
+                report.failed_episodes.push(episode);
+            }
+            TaskOutcome::Skipped(message) => report.skipped.push(message),
+            TaskOutcome::Cancelled(episode) => report.cancelled.push(episode),
         }
     }
+    total_pb.finish();
+    if let Some(series_index) = &series_index {
+        if let Err(e) = series_index.save() {
+            report.failed.push(format!("已下载页面记录保存失败: {}", e));
+        }
+    }
+    if options.transcode.is_some() {
+        report.transcode_summary = Some(transcode_stats.summary());
+    }
+    report.cancelled.sort_by(|a, b| a.ord.total_cmp(&b.ord));
+    report
+        .failed_episodes
+        .sort_by(|a, b| a.ord.total_cmp(&b.ord));
 
-    failed_message
+    Ok(report)
 }
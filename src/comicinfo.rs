@@ -0,0 +1,48 @@
+use crate::{apis::Episode, imaging::Page};
+
+pub const COMIC_INFO_FILE_NAME: &str = "ComicInfo.xml";
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/**
+ * 生成阅读器使用的 ComicInfo.xml, `<Pages>` 中记录每页的尺寸以及是否为跨页.
+ */
+pub fn build_comic_info(episode: &Episode, series: Option<&str>, pages: &[Page]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n");
+    xml.push_str(&format!(
+        "  <Title>{}</Title>\n",
+        escape_xml(&episode.title)
+    ));
+    if let Some(series) = series {
+        xml.push_str(&format!("  <Series>{}</Series>\n", escape_xml(series)));
+    }
+    xml.push_str(&format!("  <Number>{}</Number>\n", episode.ord));
+    xml.push_str(&format!("  <PageCount>{}</PageCount>\n", pages.len()));
+    xml.push_str("  <Pages>\n");
+    for (index, page) in pages.iter().enumerate() {
+        let double_page = if page.double_page {
+            " DoublePage=\"true\""
+        } else {
+            ""
+        };
+        xml.push_str(&format!(
+            "    <Page Image=\"{}\" ImageSize=\"{}\" ImageWidth=\"{}\" ImageHeight=\"{}\"{} />\n",
+            index,
+            page.bytes.len(),
+            page.width,
+            page.height,
+            double_page
+        ));
+    }
+    xml.push_str("  </Pages>\n");
+    xml.push_str("</ComicInfo>\n");
+    xml
+}
@@ -1,22 +1,174 @@
-use std::path::PathBuf;
+use std::{io::Write, path::PathBuf};
 
+use crossterm::style::Stylize;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    cookie::CookieJar,
+    credentials::{
+        decrypt_cookie, encrypt_cookie, forget_passphrase, get_passphrase, passphrase_from_env,
+        EncryptedCookie,
+    },
+    dedupe::BlockList,
+    profile::DeviceProfile,
+};
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
-    pub cookie: String,
+    /// 账号 Cookie, 兼容只保存 SESSDATA 的旧配置.
+    #[serde(default, skip_serializing_if = "CookieJar::is_empty")]
+    pub cookie: CookieJar,
+    /// 使用口令加密保存的 Cookie, 此时不再保存明文的 `cookie`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_cookie: Option<EncryptedCookie>,
+    /// 保存时是否加密 Cookie.
+    #[serde(skip)]
+    pub encrypt_cookie: bool,
     pub download_path: String,
+    /// 访问哔哩哔哩接口使用的代理.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_proxy: Option<String>,
+    /// 访问图片 CDN 使用的代理.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_proxy: Option<String>,
+    #[serde(default)]
+    pub timeout: TimeoutConfig,
+    #[serde(default)]
+    pub image_quality: ImageQuality,
+    /// 自定义的阅读设备配置.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_profiles: Vec<DeviceProfile>,
+    /// 下载时删除的屏蔽图片.
+    #[serde(default)]
+    pub block_list: BlockList,
+}
+
+/**
+ * 从 CDN 请求的图片格式.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CdnFormat {
+    Jpg,
+    Webp,
+    Avif,
+}
+
+impl CdnFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CdnFormat::Jpg => "jpg",
+            CdnFormat::Webp => "webp",
+            CdnFormat::Avif => "avif",
+        }
+    }
+}
+
+/**
+ * 图片质量设置, 都不设置时下载原图.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ImageQuality {
+    /// 图片最大宽度, 单位为像素.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_width: Option<u32>,
+    /// 优先使用的图片格式.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<CdnFormat>,
+}
+
+impl ImageQuality {
+    /**
+     * 为图片路径加上 CDN 的尺寸与格式后缀, 如 `@1100w.jpg`、`@.webp`.
+     *
+     * `width` 为原图宽度, 未超过最大宽度时不缩放.
+     */
+    pub fn apply(&self, path: &str, width: u32) -> String {
+        let max_width = self
+            .max_width
+            .filter(|max_width| width == 0 || *max_width < width);
+        if max_width.is_none() && self.format.is_none() {
+            return path.to_string();
+        }
+        let width = max_width
+            .map(|width| format!("{}w", width))
+            .unwrap_or_default();
+        let format = self.format.unwrap_or(CdnFormat::Jpg);
+        format!("{}@{}.{}", path, width, format.extension())
+    }
+}
+
+/**
+ * 网络超时设置, 单位均为秒.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// 建立连接的超时时间.
+    pub connect: u64,
+    /// 两次读取之间的超时时间.
+    pub read: u64,
+    /// 单个请求从发出到读取完毕的总超时时间.
+    pub total: u64,
+    /// 单张图片持续没有收到数据的时间超过该值时放弃本次下载并重试.
+    pub stall: u64,
+    /// 单张图片的最大重试次数.
+    pub retries: u32,
+}
+
+impl TimeoutConfig {
+    /**
+     * 检查超时设置, 除重试次数外都必须大于 0.
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("connect", self.connect),
+            ("read", self.read),
+            ("total", self.total),
+            ("stall", self.stall),
+        ];
+        for (name, value) in fields {
+            if value == 0 {
+                return Err(format!("超时设置 timeout.{} 必须大于 0", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            connect: 10,
+            read: 30,
+            total: 300,
+            stall: 20,
+            retries: 3,
+        }
+    }
 }
 
 impl Config {
     pub fn new() -> Self {
         Config {
-            cookie: "".to_string(),
+            cookie: CookieJar::default(),
+            encrypted_cookie: None,
+            encrypt_cookie: false,
             download_path: "".to_string(),
+            api_proxy: None,
+            image_proxy: None,
+            timeout: TimeoutConfig::default(),
+            image_quality: ImageQuality::default(),
+            device_profiles: vec![],
+            block_list: BlockList::default(),
         }
     }
 
-    pub fn load(config_path: &PathBuf) -> Self {
+    /**
+     * 读取配置文件, 不解密 Cookie.
+     */
+    pub fn read(config_path: &PathBuf) -> Self {
         if config_path.exists() {
             let content = std::fs::read_to_string(config_path).expect("配置文件读取失败");
             serde_json::from_str(&content).expect("配置文件解析失败")
@@ -25,8 +177,138 @@ impl Config {
         }
     }
 
+    /**
+     * 读取配置文件, Cookie 已加密时使用口令解密.
+     */
+    pub fn load(config_path: &PathBuf) -> Self {
+        let mut config = Config::read(config_path);
+        let Some(encrypted) = &config.encrypted_cookie else {
+            return config;
+        };
+
+        let mut attempts = 0;
+        config.cookie = loop {
+            let result =
+                get_passphrase(false).and_then(|passphrase| decrypt_cookie(encrypted, &passphrase));
+            match result {
+                Ok(cookie) => break cookie,
+                Err(e) => {
+                    println!("{}", e.red());
+                    forget_passphrase();
+                    attempts += 1;
+                    if passphrase_from_env() || attempts >= 3 {
+                        std::process::exit(1);
+                    }
+                }
+            }
+        };
+        config.encrypt_cookie = true;
+        config
+    }
+
+    /**
+     * 保存配置文件, 通过 `read` 读取的配置会原样保留加密的 Cookie.
+     */
     pub fn save(&self, config_path: &PathBuf) {
-        let content = serde_json::to_string_pretty(self).expect("配置文件序列化失败");
-        std::fs::write(config_path, content).expect("配置文件写入失败");
+        let mut stored = self.clone();
+        if self.encrypt_cookie {
+            let encrypted = get_passphrase(true)
+                .and_then(|passphrase| encrypt_cookie(&self.cookie, &passphrase))
+                .unwrap_or_else(|e| {
+                    println!("{}", e.red());
+                    std::process::exit(1);
+                });
+            stored.encrypted_cookie = Some(encrypted);
+            stored.cookie = CookieJar::default();
+        }
+
+        let content = serde_json::to_string_pretty(&stored).expect("配置文件序列化失败");
+        write_private_file(config_path, content.as_bytes()).expect("配置文件写入失败");
+    }
+}
+
+/**
+ * 写入只有当前用户可以读写的文件.
+ */
+fn write_private_file(path: &PathBuf, content: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // 已存在的文件不会应用 mode, 需要单独设置.
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options.open(path)?;
+    file.write_all(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn original_keeps_path() {
+        let quality = ImageQuality::default();
+        assert_eq!(quality.apply("/bfs/manga/a.jpg", 1600), "/bfs/manga/a.jpg");
+    }
+
+    #[test]
+    fn max_width_scales_wider_images() {
+        let quality = ImageQuality {
+            max_width: Some(1100),
+            format: None,
+        };
+        assert_eq!(
+            quality.apply("/bfs/manga/a.jpg", 1600),
+            "/bfs/manga/a.jpg@1100w.jpg"
+        );
+        // 宽度未知时也缩放.
+        assert_eq!(
+            quality.apply("/bfs/manga/a.jpg", 0),
+            "/bfs/manga/a.jpg@1100w.jpg"
+        );
+    }
+
+    #[test]
+    fn max_width_keeps_narrower_images() {
+        let quality = ImageQuality {
+            max_width: Some(1600),
+            format: None,
+        };
+        assert_eq!(quality.apply("/bfs/manga/a.jpg", 1100), "/bfs/manga/a.jpg");
+        assert_eq!(quality.apply("/bfs/manga/a.jpg", 1600), "/bfs/manga/a.jpg");
+    }
+
+    #[test]
+    fn format_without_max_width() {
+        let quality = ImageQuality {
+            max_width: None,
+            format: Some(CdnFormat::Webp),
+        };
+        assert_eq!(
+            quality.apply("/bfs/manga/a.jpg", 1600),
+            "/bfs/manga/a.jpg@.webp"
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn format_with_max_width() {
+        let quality = ImageQuality {
+            max_width: Some(1100),
+            format: Some(CdnFormat::Avif),
+        };
+        assert_eq!(
+            quality.apply("/bfs/manga/a.jpg", 1600),
+            "/bfs/manga/a.jpg@1100w.avif"
+        );
+        // 未超过最大宽度时只转换格式.
+        assert_eq!(
+            quality.apply("/bfs/manga/a.jpg", 800),
+            "/bfs/manga/a.jpg@.avif"
+        );
+    }
+}
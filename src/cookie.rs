@@ -0,0 +1,226 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/**
+ * 哔哩哔哩账号的 Cookie, 包含 SESSDATA、bili_jct、DedeUserID、buvid3 等.
+ *
+ * 克隆后共享同一份 Cookie, 接口响应中的 `Set-Cookie` 会写回这里,
+ * 调用 `take_changed` 判断是否需要保存到配置文件.
+ */
+#[derive(Clone, Default)]
+pub struct CookieJar {
+    inner: Arc<Mutex<CookieJarInner>>,
+}
+
+#[derive(Debug, Default)]
+struct CookieJarInner {
+    cookies: BTreeMap<String, String>,
+    changed: bool,
+}
+
+impl CookieJar {
+    /**
+     * 解析 `SESSDATA=xxx; bili_jct=xxx` 形式的 Cookie, 只有值时视为 SESSDATA.
+     */
+    pub fn parse(cookie: &str) -> Self {
+        let cookie = cookie.trim();
+        let mut cookies = BTreeMap::new();
+        if !cookie.is_empty() && !cookie.contains('=') {
+            cookies.insert("SESSDATA".to_string(), cookie.to_string());
+        } else {
+            for pair in cookie.split(';') {
+                if let Some((name, value)) = pair.split_once('=') {
+                    let name = name.trim();
+                    if !name.is_empty() {
+                        cookies.insert(name.to_string(), value.trim().to_string());
+                    }
+                }
+            }
+        }
+        Self::from_map(cookies)
+    }
+
+    pub fn from_map(cookies: BTreeMap<String, String>) -> Self {
+        CookieJar {
+            inner: Arc::new(Mutex::new(CookieJarInner {
+                cookies,
+                changed: false,
+            })),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.inner.lock().unwrap().cookies.get(name).cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().cookies.is_empty()
+    }
+
+    /**
+     * 需要 CSRF 校验的接口使用的 token, 即 bili_jct.
+     */
+    pub fn csrf(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.get("bili_jct")
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| "Cookie 中缺少 bili_jct, 请重新登录".into())
+    }
+
+    /**
+     * SESSDATA 的过期时间, 为 Unix 时间戳, 单位为秒.
+     *
+     * SESSDATA 的格式为 `token%2C过期时间%2C签名`.
+     */
+    pub fn sessdata_expires_at(&self) -> Option<u64> {
+        let sessdata = self.get("SESSDATA")?;
+        let sessdata = sessdata.replace("%2C", ",").replace("%2c", ",");
+        sessdata.split(',').nth(1)?.parse().ok()
+    }
+
+    /**
+     * 用于请求头的 Cookie 字符串.
+     */
+    pub fn header_value(&self) -> String {
+        self.inner
+            .lock()
+            .unwrap()
+            .cookies
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /**
+     * 写入响应中的 `Set-Cookie`, 过期的 Cookie 会被删除.
+     */
+    pub fn store_response(&self, headers: &HeaderMap) {
+        let mut inner = self.inner.lock().unwrap();
+        for value in headers.get_all(SET_COOKIE) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            let mut attributes = value.split(';');
+            let Some((name, value)) = attributes.next().and_then(|pair| pair.split_once('='))
+            else {
+                continue;
+            };
+            let (name, value) = (name.trim(), value.trim());
+            let expired = value.is_empty()
+                || attributes.any(|attribute| {
+                    attribute
+                        .trim()
+                        .to_ascii_lowercase()
+                        .strip_prefix("max-age=")
+                        .is_some_and(|max_age| max_age.trim().starts_with(['0', '-']))
+                });
+
+            let changed = if expired {
+                inner.cookies.remove(name).is_some()
+            } else {
+                inner.cookies.insert(name.to_string(), value.to_string()) != Some(value.to_string())
+            };
+            inner.changed |= changed;
+        }
+    }
+
+    /**
+     * 返回上次调用后 Cookie 是否有变化.
+     */
+    pub fn take_changed(&self) -> bool {
+        std::mem::take(&mut self.inner.lock().unwrap().changed)
+    }
+}
+
+/**
+ * 只输出 Cookie 名称, 避免在日志中泄露.
+ */
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        let mut map = f.debug_map();
+        for name in inner.cookies.keys() {
+            map.entry(name, &"<redacted>");
+        }
+        map.finish()
+    }
+}
+
+impl Serialize for CookieJar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.lock().unwrap().cookies.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CookieJar {
+    /**
+     * 兼容旧配置文件中只保存 SESSDATA 的字符串.
+     */
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CookieJarVisitor;
+
+        impl<'de> de::Visitor<'de> for CookieJarVisitor {
+            type Value = CookieJar;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("cookie string or map")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<CookieJar, E> {
+                Ok(CookieJar::parse(value))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<CookieJar, A::Error> {
+                let cookies = BTreeMap::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(CookieJar::from_map(cookies))
+            }
+        }
+
+        deserializer.deserialize_any(CookieJarVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_full_cookie() {
+        let cookie =
+            CookieJar::parse(" SESSDATA=abc%2C1700000000%2Cxyz; bili_jct=token ;DedeUserID=42; ");
+        assert_eq!(
+            cookie.get("SESSDATA").as_deref(),
+            Some("abc%2C1700000000%2Cxyz")
+        );
+        assert_eq!(cookie.get("bili_jct").as_deref(), Some("token"));
+        assert_eq!(cookie.get("DedeUserID").as_deref(), Some("42"));
+        assert_eq!(cookie.sessdata_expires_at(), Some(1700000000));
+    }
+
+    #[test]
+    fn parse_bare_sessdata() {
+        let cookie = CookieJar::parse("abc%2C1700000000");
+        assert_eq!(cookie.get("SESSDATA").as_deref(), Some("abc%2C1700000000"));
+        assert_eq!(cookie.header_value(), "SESSDATA=abc%2C1700000000");
+    }
+
+    #[test]
+    fn parse_keeps_equals_in_value() {
+        let cookie = CookieJar::parse("a=b=c; =ignored; novalue");
+        assert_eq!(cookie.get("a").as_deref(), Some("b=c"));
+        assert_eq!(cookie.header_value(), "a=b=c");
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(CookieJar::parse("").is_empty());
+        assert!(CookieJar::parse("  ").is_empty());
+        assert!(CookieJar::parse("").csrf().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::cookie::CookieJar;
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/**
+ * 从浏览器导出的 Cookie 文件中读取 bilibili.com 的 Cookie.
+ *
+ * 支持 Netscape 格式的 cookies.txt、Firefox 的 cookies.sqlite 与 Chromium 的 Cookies 数据库,
+ * 也可以传入 Firefox 或 Chromium 的配置目录.
+ */
+pub fn import_cookie_file(path: &Path) -> Result<CookieJar, Box<dyn std::error::Error>> {
+    let path = resolve_profile_dir(path)?;
+
+    let mut header = [0u8; SQLITE_HEADER.len()];
+    let is_sqlite =
+        fs::File::open(&path)?.read_exact(&mut header).is_ok() && header == SQLITE_HEADER;
+
+    let cookies = if is_sqlite {
+        read_sqlite_cookies(&path)?
+    } else {
+        read_netscape_cookies(&fs::read_to_string(&path)?)
+    };
+
+    if !cookies.contains_key("SESSDATA") {
+        return Err("没有找到 bilibili.com 的 SESSDATA, 请确认浏览器已登录".into());
+    }
+
+    Ok(CookieJar::from_map(cookies))
+}
+
+/**
+ * 传入的是浏览器配置目录时找到其中的 Cookie 数据库.
+ */
+fn resolve_profile_dir(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    ["cookies.sqlite", "Network/Cookies", "Cookies"]
+        .iter()
+        .map(|name| path.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("目录中没有找到 Cookie 数据库: {}", path.display()).into())
+}
+
+fn is_bilibili_host(host: &str) -> bool {
+    let host = host.trim_start_matches('.');
+    host == "bilibili.com" || host.ends_with(".bilibili.com")
+}
+
+/**
+ * 解析 Netscape 格式的 cookies.txt, 跳过已过期的 Cookie.
+ */
+fn read_netscape_cookies(content: &str) -> BTreeMap<String, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut cookies = BTreeMap::new();
+    for line in content.lines() {
+        // curl 等工具用 `#HttpOnly_` 前缀标记 HttpOnly 的 Cookie.
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 7 || !is_bilibili_host(fields[0]) {
+            continue;
+        }
+        let expires: u64 = fields[4].trim().parse().unwrap_or(0);
+        if expires != 0 && expires < now {
+            continue;
+        }
+        cookies.insert(fields[5].to_string(), fields[6].trim_end().to_string());
+    }
+    cookies
+}
+
+/**
+ * 读取 Firefox 或 Chromium 的 Cookie 数据库.
+ *
+ * 以只读方式打开, 浏览器正在运行时也可以读取.
+ */
+fn read_sqlite_cookies(
+    path: &Path,
+) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let path = path.canonicalize()?;
+    let uri = format!(
+        "file:{}?immutable=1",
+        path.to_string_lossy()
+            .replace('%', "%25")
+            .replace('?', "%3f")
+            .replace('#', "%23")
+    );
+    let conn = Connection::open_with_flags(
+        uri,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )?;
+
+    let is_firefox = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'moz_cookies'")?
+        .exists([])?;
+    let query = if is_firefox {
+        "SELECT host, name, value, 0 FROM moz_cookies ORDER BY lastAccessed"
+    } else {
+        "SELECT host_key, name, value, length(encrypted_value) FROM cookies ORDER BY last_access_utc"
+    };
+
+    let mut cookies = BTreeMap::new();
+    let mut encrypted = false;
+    let mut stmt = conn.prepare(query)?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let host: String = row.get(0)?;
+        if !is_bilibili_host(&host) {
+            continue;
+        }
+        let name: String = row.get(1)?;
+        let value: String = row.get(2)?;
+        let encrypted_len: i64 = row.get::<_, Option<i64>>(3)?.unwrap_or(0);
+        if value.is_empty() && encrypted_len > 0 {
+            encrypted = true;
+            continue;
+        }
+        cookies.insert(name, value);
+    }
+
+    // 新版 Chromium 使用系统密钥加密 Cookie, 无法直接读取.
+    if encrypted && !cookies.contains_key("SESSDATA") {
+        return Err("Chromium 的 Cookie 已加密, 请使用浏览器扩展导出 cookies.txt 后再导入".into());
+    }
+
+    Ok(cookies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netscape_cookies_bilibili_only() {
+        let content = "# Netscape HTTP Cookie File\n\
+            \n\
+            .bilibili.com\tTRUE\t/\tFALSE\t0\tbuvid3\tabc\n\
+            #HttpOnly_.bilibili.com\tTRUE\t/\tTRUE\t0\tSESSDATA\txyz%2C1\r\n\
+            manga.bilibili.com\tFALSE\t/\tFALSE\t0\tbili_jct\ttoken\n\
+            .notbilibili.com\tTRUE\t/\tFALSE\t0\tother\tvalue\n\
+            .example.com\tTRUE\t/\tFALSE\t0\tSESSDATA\tleaked\n";
+        let cookies = read_netscape_cookies(content);
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies["buvid3"], "abc");
+        assert_eq!(cookies["SESSDATA"], "xyz%2C1");
+        assert_eq!(cookies["bili_jct"], "token");
+    }
+
+    #[test]
+    fn netscape_cookies_skip_expired_and_malformed() {
+        let content = ".bilibili.com\tTRUE\t/\tFALSE\t1\texpired\tvalue\n\
+            .bilibili.com\tTRUE\t/\tFALSE\t32503680000\tvalid\tvalue\n\
+            .bilibili.com\tTRUE\t/\tFALSE\t0\n";
+        let cookies = read_netscape_cookies(content);
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies["valid"], "value");
+    }
+
+    #[test]
+    fn bilibili_hosts() {
+        assert!(is_bilibili_host("bilibili.com"));
+        assert!(is_bilibili_host(".bilibili.com"));
+        assert!(is_bilibili_host("manga.bilibili.com"));
+        assert!(!is_bilibili_host("notbilibili.com"));
+        assert!(!is_bilibili_host("bilibili.com.example.com"));
+    }
+}
@@ -0,0 +1,194 @@
+use std::{fmt, io::IsTerminal, sync::Mutex};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use dialoguer::{theme::ColorfulTheme, Password};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::cookie::CookieJar;
+
+/**
+ * 无人值守运行时从该环境变量读取口令.
+ */
+pub const PASSPHRASE_ENV: &str = "BILI_MANGA_PASSPHRASE";
+
+/// 本次运行中已输入的口令, 避免重复询问.
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/**
+ * 使用口令加密的 Cookie, 各字段均为 base64.
+ *
+ * 口令经 Argon2id 派生密钥后使用 ChaCha20-Poly1305 加密.
+ */
+#[derive(Deserialize, Serialize, Clone)]
+pub struct EncryptedCookie {
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+impl fmt::Debug for EncryptedCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptedCookie(<redacted>)")
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("口令密钥派生失败: {}", e))?;
+    Ok(key)
+}
+
+pub fn encrypt_cookie(cookie: &CookieJar, passphrase: &str) -> Result<EncryptedCookie, String> {
+    let mut rng = rand::thread_rng();
+    let salt: [u8; 16] = rng.gen();
+    let nonce: [u8; 12] = rng.gen();
+
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = serde_json::to_vec(cookie).map_err(|e| e.to_string())?;
+    let data = ChaCha20Poly1305::new(&key)
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| "Cookie 加密失败".to_string())?;
+
+    Ok(EncryptedCookie {
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        data: STANDARD.encode(data),
+    })
+}
+
+pub fn decrypt_cookie(encrypted: &EncryptedCookie, passphrase: &str) -> Result<CookieJar, String> {
+    let decode = |value: &str| {
+        STANDARD
+            .decode(value)
+            .map_err(|_| "加密的 Cookie 已损坏".to_string())
+    };
+    let salt = decode(&encrypted.salt)?;
+    let nonce = decode(&encrypted.nonce)?;
+    let data = decode(&encrypted.data)?;
+    if nonce.len() != 12 {
+        return Err("加密的 Cookie 已损坏".to_string());
+    }
+
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(&nonce), data.as_slice())
+        .map_err(|_| "口令错误".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|_| "加密的 Cookie 已损坏".to_string())
+}
+
+/**
+ * 获取口令, 依次使用本次运行已输入的口令、环境变量与终端输入.
+ *
+ * `confirm` 为 true 时要求输入两次, 用于首次设置口令.
+ */
+pub fn get_passphrase(confirm: bool) -> Result<String, String> {
+    if let Some(passphrase) = PASSPHRASE.lock().unwrap().clone() {
+        return Ok(passphrase);
+    }
+
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) if !passphrase.is_empty() => passphrase,
+        _ => {
+            if !std::io::stdin().is_terminal() {
+                return Err(format!(
+                    "无法输入口令, 请通过环境变量 {} 提供 Cookie 口令",
+                    PASSPHRASE_ENV
+                ));
+            }
+            let theme = ColorfulTheme::default();
+            let prompt = Password::with_theme(&theme).with_prompt("请输入 Cookie 口令");
+            let prompt = if confirm {
+                prompt.with_confirmation("请再次输入口令", "两次输入的口令不一致")
+            } else {
+                prompt
+            };
+            prompt.interact().map_err(|e| e.to_string())?
+        }
+    };
+
+    *PASSPHRASE.lock().unwrap() = Some(passphrase.clone());
+    Ok(passphrase)
+}
+
+/**
+ * 口令错误时清除已记住的口令, 以便重新输入.
+ */
+pub fn forget_passphrase() {
+    *PASSPHRASE.lock().unwrap() = None;
+}
+
+/**
+ * 口令是否来自环境变量, 此时口令错误不再重新询问.
+ */
+pub fn passphrase_from_env() -> bool {
+    std::env::var(PASSPHRASE_ENV).is_ok_and(|passphrase| !passphrase.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie() -> CookieJar {
+        CookieJar::parse("SESSDATA=abc%2C1700000000; bili_jct=token")
+    }
+
+    #[test]
+    fn round_trip() {
+        let encrypted = encrypt_cookie(&cookie(), "passphrase").unwrap();
+        let decrypted = decrypt_cookie(&encrypted, "passphrase").unwrap();
+        assert_eq!(decrypted.header_value(), cookie().header_value());
+    }
+
+    #[test]
+    fn different_salt_and_nonce_each_time() {
+        let first = encrypt_cookie(&cookie(), "passphrase").unwrap();
+        let second = encrypt_cookie(&cookie(), "passphrase").unwrap();
+        assert_ne!(first.salt, second.salt);
+        assert_ne!(first.nonce, second.nonce);
+        assert_ne!(first.data, second.data);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let encrypted = encrypt_cookie(&cookie(), "passphrase").unwrap();
+        let e = decrypt_cookie(&encrypted, "wrong").unwrap_err();
+        assert_eq!(e, "口令错误");
+    }
+
+    #[test]
+    fn tampered_data() {
+        let mut encrypted = encrypt_cookie(&cookie(), "passphrase").unwrap();
+        let mut data = STANDARD.decode(&encrypted.data).unwrap();
+        data[0] ^= 1;
+        encrypted.data = STANDARD.encode(data);
+        // 密文被修改时无法与口令错误区分.
+        assert!(decrypt_cookie(&encrypted, "passphrase").is_err());
+    }
+
+    #[test]
+    fn corrupted_fields() {
+        let encrypted = encrypt_cookie(&cookie(), "passphrase").unwrap();
+
+        let short_nonce = EncryptedCookie {
+            nonce: STANDARD.encode([0u8; 8]),
+            ..encrypted.clone()
+        };
+        let e = decrypt_cookie(&short_nonce, "passphrase").unwrap_err();
+        assert!(e.contains("已损坏"), "{}", e);
+
+        let invalid_base64 = EncryptedCookie {
+            salt: "not base64!".to_string(),
+            ..encrypted
+        };
+        let e = decrypt_cookie(&invalid_base64, "passphrase").unwrap_err();
+        assert!(e.contains("已损坏"), "{}", e);
+    }
+}
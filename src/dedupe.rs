@@ -0,0 +1,299 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use image::{imageops::FilterType, DynamicImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    imaging::Page,
+    manifest::sha256_hex,
+    utils::{get_part_path, sync_dir},
+};
+
+const SERIES_INDEX_FILE_NAME: &str = ".page_hashes.json";
+
+/**
+ * 屏蔽的图片, 如每章末尾重复出现的招募、公告图.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BlockedPage {
+    /// 感知哈希, 16 位十六进制.
+    pub hash: String,
+    /// 登记时的文件名, 仅用于展示.
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct BlockList {
+    /// 感知哈希的汉明距离不超过该值时视为同一张图片.
+    pub threshold: u32,
+    pub pages: Vec<BlockedPage>,
+}
+
+impl Default for BlockList {
+    fn default() -> Self {
+        BlockList {
+            threshold: 6,
+            pages: vec![],
+        }
+    }
+}
+
+impl BlockList {
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+/**
+ * 计算 64 位的差异哈希 (dHash), 缩放与轻微压缩不会影响结果.
+ */
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y).0[0];
+            let right = small.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | (left < right) as u64;
+        }
+    }
+    hash
+}
+
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+fn parse_hash(hash: &str) -> Option<u64> {
+    u64::from_str_radix(hash, 16).ok()
+}
+
+/**
+ * 一部漫画已下载页面的 SHA-256, 按章节 ID 记录, 用于删除跨章节完全相同的页面.
+ *
+ * 旧版本的索引没有章节 ID, 无法区分重新下载的章节, 读取时忽略.
+ */
+#[derive(Debug)]
+pub struct SeriesIndex {
+    path: PathBuf,
+    episodes: Mutex<BTreeMap<u32, Vec<String>>>,
+}
+
+impl SeriesIndex {
+    pub fn load(dest_path: &Path) -> Self {
+        let path = dest_path.join(SERIES_INDEX_FILE_NAME);
+        let episodes = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        SeriesIndex {
+            path,
+            episodes: Mutex::new(episodes),
+        }
+    }
+
+    /**
+     * 返回每一页是否与其他章节或本章节之前的页面重复, 并将保留的页面记为该章节的页面.
+     *
+     * 检查与记录在同一次加锁中完成, 同时下载的章节不会互相漏掉重复的页面.
+     * 重新下载时忽略该章节原有的记录.
+     */
+    fn claim(&self, episode_id: u32, sha256s: &[String]) -> Vec<bool> {
+        let mut episodes = self.episodes.lock().unwrap();
+        let mut seen: HashSet<&str> = episodes
+            .iter()
+            .filter(|(id, _)| **id != episode_id)
+            .flat_map(|(_, hashes)| hashes.iter().map(|hash| hash.as_str()))
+            .collect();
+        let duplicates: Vec<bool> = sha256s
+            .iter()
+            .map(|sha256| !seen.insert(sha256.as_str()))
+            .collect();
+        let kept = sha256s
+            .iter()
+            .zip(&duplicates)
+            .filter(|(_, duplicate)| !**duplicate)
+            .map(|(sha256, _)| sha256.clone())
+            .collect();
+        episodes.insert(episode_id, kept);
+        duplicates
+    }
+
+    /**
+     * 章节没有写入压缩文件时删除其记录, 避免其他章节因此删除页面.
+     */
+    pub fn remove(&self, episode_id: u32) {
+        self.episodes.lock().unwrap().remove(&episode_id);
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string(&*self.episodes.lock().unwrap())?;
+        let part_path = get_part_path(&self.path);
+        std::fs::write(&part_path, content)?;
+        std::fs::rename(&part_path, &self.path)?;
+        sync_dir(&self.path);
+        Ok(())
+    }
+}
+
+/**
+ * 删除与屏蔽图片相似的页面, 以及同一部漫画中已经下载过的完全相同的页面.
+ */
+#[derive(Debug, Default)]
+pub struct PageFilter {
+    pub blocked: Vec<(u64, String)>,
+    pub threshold: u32,
+    pub series: Option<std::sync::Arc<SeriesIndex>>,
+}
+
+impl PageFilter {
+    pub fn new(block_list: &BlockList, series: Option<std::sync::Arc<SeriesIndex>>) -> Self {
+        PageFilter {
+            blocked: block_list
+                .pages
+                .iter()
+                .filter_map(|page| Some((parse_hash(&page.hash)?, page.name.clone())))
+                .collect(),
+            threshold: block_list.threshold,
+            series,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty() && self.series.is_none()
+    }
+
+    fn matches_blocked(&self, page: &Page) -> Option<&str> {
+        if self.blocked.is_empty() {
+            return None;
+        }
+        let image = image::load_from_memory(&page.bytes).ok()?;
+        let hash = dhash(&image);
+        self.blocked
+            .iter()
+            .find(|(blocked, _)| (blocked ^ hash).count_ones() <= self.threshold)
+            .map(|(_, name)| name.as_str())
+    }
+
+    /**
+     * 返回保留的页面与删除原因, 保留的页面会记入该章节的已下载页面.
+     */
+    pub fn filter(&self, episode_id: u32, pages: Vec<Page>) -> (Vec<Page>, Vec<String>) {
+        let mut candidates = vec![];
+        let mut dropped = vec![];
+        for (index, page) in pages.into_iter().enumerate() {
+            if let Some(name) = self.matches_blocked(&page) {
+                dropped.push(format!("第 {} 页与屏蔽图片 {} 相似", index + 1, name));
+                continue;
+            }
+            candidates.push((index, page));
+        }
+
+        let Some(series) = &self.series else {
+            let kept = candidates.into_iter().map(|(_, page)| page).collect();
+            return (kept, dropped);
+        };
+        let sha256s: Vec<String> = candidates
+            .iter()
+            .map(|(_, page)| sha256_hex(&page.bytes))
+            .collect();
+        let duplicates = series.claim(episode_id, &sha256s);
+        let mut kept = vec![];
+        for ((index, page), duplicate) in candidates.into_iter().zip(duplicates) {
+            if duplicate {
+                dropped.push(format!("第 {} 页与已下载的页面重复", index + 1));
+            } else {
+                kept.push(page);
+            }
+        }
+        (kept, dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::imaging::PageFormat;
+
+    fn page(content: &'static [u8]) -> Page {
+        Page {
+            bytes: Bytes::from_static(content),
+            format: PageFormat::Jpeg,
+            width: 1,
+            height: 1,
+            double_page: false,
+        }
+    }
+
+    fn series_filter() -> PageFilter {
+        let series = SeriesIndex::load(Path::new("/nonexistent"));
+        PageFilter::new(&BlockList::default(), Some(std::sync::Arc::new(series)))
+    }
+
+    fn contents(pages: &[Page]) -> Vec<&[u8]> {
+        pages.iter().map(|page| page.bytes.as_ref()).collect()
+    }
+
+    #[test]
+    fn drops_pages_from_other_episodes() {
+        let filter = series_filter();
+        let (kept, dropped) = filter.filter(1, vec![page(b"a"), page(b"ad")]);
+        assert_eq!(contents(&kept), vec![b"a".as_ref(), b"ad"]);
+        assert!(dropped.is_empty());
+
+        let (kept, dropped) = filter.filter(2, vec![page(b"b"), page(b"ad")]);
+        assert_eq!(contents(&kept), vec![b"b".as_ref()]);
+        assert_eq!(dropped, vec!["第 2 页与已下载的页面重复"]);
+    }
+
+    #[test]
+    fn drops_repeated_pages_within_episode() {
+        let filter = series_filter();
+        let (kept, dropped) = filter.filter(1, vec![page(b"a"), page(b"a")]);
+        assert_eq!(contents(&kept), vec![b"a".as_ref()]);
+        assert_eq!(dropped.len(), 1);
+    }
+
+    #[test]
+    fn redownload_keeps_own_pages() {
+        let filter = series_filter();
+        filter.filter(1, vec![page(b"a"), page(b"b")]);
+        filter.filter(2, vec![page(b"c")]);
+
+        // 删除压缩文件或校验失败后重新下载同一章节.
+        let (kept, dropped) = filter.filter(1, vec![page(b"a"), page(b"b")]);
+        assert_eq!(contents(&kept), vec![b"a".as_ref(), b"b"]);
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn removed_episode_no_longer_drops_pages() {
+        let filter = series_filter();
+        filter.filter(1, vec![page(b"a")]);
+        filter.series.as_ref().unwrap().remove(1);
+
+        let (kept, _) = filter.filter(2, vec![page(b"a")]);
+        assert_eq!(contents(&kept), vec![b"a".as_ref()]);
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let dir = std::env::temp_dir().join(format!("series-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = SeriesIndex::load(&dir);
+        index.claim(7, &["x".to_string()]);
+        index.save().unwrap();
+
+        let index = SeriesIndex::load(&dir);
+        assert_eq!(index.claim(8, &["x".to_string()]), vec![true]);
+        assert_eq!(index.claim(7, &["x".to_string()]), vec![false]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+use std::{error::Error, sync::OnceLock, time::Duration};
+
+use reqwest::{Client, Proxy};
+
+use crate::config::TimeoutConfig;
+
+static API_CLIENT: OnceLock<Client> = OnceLock::new();
+static IMAGE_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/**
+ * 网络代理设置, 支持 http://、https://、socks5:// 与 socks5h:// 地址.
+ *
+ * 未设置时使用 HTTPS_PROXY、ALL_PROXY 等环境变量.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ProxyOptions {
+    /// 访问哔哩哔哩接口使用的代理.
+    pub api_proxy: Option<String>,
+    /// 访问图片 CDN 使用的代理.
+    pub image_proxy: Option<String>,
+}
+
+fn build_client(
+    proxy: Option<&str>,
+    timeout: &TimeoutConfig,
+    total_timeout: bool,
+) -> Result<Client, Box<dyn Error>> {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(timeout.connect))
+        .read_timeout(Duration::from_secs(timeout.read));
+    if total_timeout {
+        builder = builder.timeout(Duration::from_secs(timeout.total));
+    }
+    if let Some(proxy) = proxy {
+        // reqwest 的错误信息只有 "builder error", 具体原因在 source 中.
+        let proxy = Proxy::all(proxy).map_err(|e| {
+            let mut cause: &dyn Error = &e;
+            while let Some(source) = cause.source() {
+                cause = source;
+            }
+            format!("{}: {}", proxy, cause)
+        })?;
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build()?)
+}
+
+/**
+ * 初始化接口与图片下载共享的 Client, 需要在发出任何请求前调用.
+ *
+ * 限速时大图片的下载时间取决于带宽上限, 图片下载不设置总超时, 由停滞超时兜底.
+ */
+pub fn init_clients(
+    proxy: &ProxyOptions,
+    timeout: &TimeoutConfig,
+    max_rate: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let api_client = build_client(proxy.api_proxy.as_deref(), timeout, true)?;
+    let image_client = build_client(proxy.image_proxy.as_deref(), timeout, max_rate.is_none())?;
+    API_CLIENT.set(api_client).map_err(|_| "Client 已初始化")?;
+    IMAGE_CLIENT
+        .set(image_client)
+        .map_err(|_| "Client 已初始化")?;
+    Ok(())
+}
+
+/**
+ * 访问哔哩哔哩接口的 Client.
+ */
+pub fn api_client() -> &'static Client {
+    API_CLIENT.get_or_init(Client::new)
+}
+
+/**
+ * 下载图片的 Client.
+ */
+pub fn image_client() -> &'static Client {
+    IMAGE_CLIENT.get_or_init(Client::new)
+}
@@ -0,0 +1,198 @@
+use std::io::Cursor;
+
+use bytes::Bytes;
+use image::ImageReader;
+
+/**
+ * 根据文件头识别的图片格式.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+    Avif,
+    Jxl,
+}
+
+impl PageFormat {
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(PageFormat::Jpeg)
+        } else if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+            Some(PageFormat::Png)
+        } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(PageFormat::Webp)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(PageFormat::Gif)
+        } else if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && &bytes[8..11] == b"avi" {
+            Some(PageFormat::Avif)
+        } else if bytes.starts_with(&[0xFF, 0x0A])
+            || bytes.starts_with(&[
+                0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+            ])
+        {
+            Some(PageFormat::Jxl)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PageFormat::Jpeg => "jpg",
+            PageFormat::Png => "png",
+            PageFormat::Webp => "webp",
+            PageFormat::Gif => "gif",
+            PageFormat::Avif => "avif",
+            PageFormat::Jxl => "jxl",
+        }
+    }
+}
+
+/**
+ * 下载并校验通过的一页图片.
+ */
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub bytes: Bytes,
+    pub format: PageFormat,
+    pub width: u32,
+    pub height: u32,
+    /// 是否为跨页, 下载时横向的页面视为跨页, 旋转等处理后保持不变.
+    pub double_page: bool,
+}
+
+/**
+ * 校验下载的图片, 可选完整解码一次以发现截断或损坏的数据.
+ *
+ * `expected_size` 为图片索引中记录的宽高, 下载的图片宽高比与之不符时视为错误.
+ */
+pub fn validate_page(
+    bytes: Bytes,
+    expected_size: Option<(u32, u32)>,
+    full_decode: bool,
+) -> Result<Page, String> {
+    let format = PageFormat::detect(&bytes).ok_or_else(|| {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(16)]).to_string();
+        format!("不是有效的图片数据: {:?}", head)
+    })?;
+
+    // JPEG 解码器会容忍截断的数据, 需要检查结束标记, 忽略末尾的填充.
+    if format == PageFormat::Jpeg {
+        let end = bytes.iter().rposition(|byte| *byte != 0).unwrap_or(0);
+        if end == 0 || bytes[end - 1..=end] != [0xFF, 0xD9] {
+            return Err("图片数据不完整: 缺少 JPEG 结束标记".to_string());
+        }
+    }
+
+    // AVIF 与 JPEG XL 没有启用解码器, 只校验文件头, 宽高取图片索引中的值.
+    let (width, height) = if matches!(format, PageFormat::Avif | PageFormat::Jxl) {
+        expected_size.unwrap_or((0, 0))
+    } else if full_decode {
+        let image = image::load_from_memory(&bytes).map_err(|e| format!("图片解码失败: {}", e))?;
+        (image.width(), image.height())
+    } else {
+        ImageReader::new(Cursor::new(&bytes))
+            .with_guessed_format()
+            .map_err(|e| e.to_string())?
+            .into_dimensions()
+            .map_err(|e| format!("图片尺寸读取失败: {}", e))?
+    };
+
+    if let Some((expected_width, expected_height)) = expected_size {
+        if expected_width > 0 && expected_height > 0 && width > 0 && height > 0 {
+            let expected_ratio = expected_width as f64 / expected_height as f64;
+            let ratio = width as f64 / height as f64;
+            if (ratio - expected_ratio).abs() / expected_ratio > 0.02 {
+                return Err(format!(
+                    "图片尺寸 {}x{} 与索引中的 {}x{} 不符",
+                    width, height, expected_width, expected_height
+                ));
+            }
+        }
+    }
+
+    Ok(Page {
+        bytes,
+        format,
+        width,
+        height,
+        double_page: width > height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::jpeg::JpegEncoder, ImageEncoder, RgbImage};
+
+    use super::*;
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 7) as u8, (y * 3) as u8, ((x + y) * 5) as u8])
+        });
+        let mut buf = vec![];
+        JpegEncoder::new_with_quality(&mut buf, 90)
+            .write_image(&image, width, height, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(PageFormat::detect(&jpeg(8, 8)), Some(PageFormat::Jpeg));
+        assert_eq!(
+            PageFormat::detect(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(PageFormat::Webp)
+        );
+        assert_eq!(PageFormat::detect(b"GIF89a"), Some(PageFormat::Gif));
+        assert_eq!(
+            PageFormat::detect(b"\0\0\0\x1cftypavif"),
+            Some(PageFormat::Avif)
+        );
+        assert_eq!(PageFormat::detect(b""), None);
+    }
+
+    #[test]
+    fn rejects_error_page() {
+        let body = Bytes::from_static(b"<html><body>403 Forbidden</body></html>");
+        let e = validate_page(body, None, false).unwrap_err();
+        assert!(e.contains("不是有效的图片数据"), "{}", e);
+    }
+
+    #[test]
+    fn jpeg_with_trailing_padding() {
+        let mut bytes = jpeg(16, 16);
+        bytes.extend([0, 0, 0]);
+        assert!(validate_page(Bytes::from(bytes), None, true).is_ok());
+    }
+
+    #[test]
+    fn valid_page() {
+        let page = validate_page(Bytes::from(jpeg(60, 40)), Some((1200, 800)), true).unwrap();
+        assert_eq!(page.format, PageFormat::Jpeg);
+        assert_eq!((page.width, page.height), (60, 40));
+        assert!(page.double_page);
+    }
+
+    #[test]
+    fn truncated_jpeg_fails_full_decode() {
+        let mut bytes = jpeg(64, 64);
+        bytes.truncate(bytes.len() / 2);
+        let bytes = Bytes::from(bytes);
+        for full_decode in [false, true] {
+            let e = validate_page(bytes.clone(), None, full_decode).unwrap_err();
+            assert!(e.contains("不完整"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn rejects_aspect_mismatch() {
+        let e = validate_page(Bytes::from(jpeg(40, 60)), Some((1200, 800)), false).unwrap_err();
+        assert!(e.contains("不符"), "{}", e);
+        // 只差缩放时视为同一张图片.
+        assert!(validate_page(Bytes::from(jpeg(40, 60)), Some((800, 1200)), false).is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossterm::style::Stylize;
+use qrcode::{render::unicode, QrCode};
+
+use crate::apis::{generate_login_qrcode, poll_login_qrcode, QrcodeLoginStatus, UserInfo, VipInfo};
+use crate::cookie::CookieJar;
+
+/**
+ * 在终端显示二维码, 等待用户使用哔哩哔哩 App 扫码确认, 返回登录后的 Cookie.
+ */
+pub async fn qrcode_login() -> Result<CookieJar, Box<dyn std::error::Error>> {
+    let qrcode = generate_login_qrcode().await?;
+
+    let code = QrCode::new(qrcode.url.as_bytes())?;
+    let image = code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .quiet_zone(true)
+        .build();
+    println!("{}", image);
+    println!("{}", "请使用哔哩哔哩 App 扫描二维码登录".cyan());
+
+    let mut scanned = false;
+    loop {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        match poll_login_qrcode(&qrcode.qrcode_key).await? {
+            QrcodeLoginStatus::Waiting => {}
+            QrcodeLoginStatus::Scanned => {
+                if !scanned {
+                    println!("{}", "已扫码, 请在手机上确认登录".cyan());
+                    scanned = true;
+                }
+            }
+            QrcodeLoginStatus::Expired => return Err("二维码已失效, 请重新登录".into()),
+            QrcodeLoginStatus::Confirmed(cookies) => {
+                if cookies.get("SESSDATA").is_none() {
+                    return Err("登录成功但没有获取到 SESSDATA".into());
+                }
+                return Ok(cookies);
+            }
+        }
+    }
+}
+
+/**
+ * 登录即将过期时提前提醒的天数.
+ */
+const EXPIRY_WARNING_DAYS: u64 = 7;
+
+fn days_until(timestamp: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    timestamp.saturating_sub(now) / 86400
+}
+
+/**
+ * 输出当前登录的用户, 登录即将过期时提醒重新登录.
+ */
+pub fn print_session(user: &UserInfo, cookie: &CookieJar) {
+    let vip = if user.is_vip() {
+        let vip_name = if user.vip_type == 2 {
+            "年度大会员"
+        } else {
+            "大会员"
+        };
+        format!(
+            ", {} 剩余 {} 天",
+            vip_name,
+            days_until(user.vip_due_date / 1000)
+        )
+    } else {
+        String::new()
+    };
+    println!(
+        "当前登录: {} (UID {}){}",
+        user.uname.as_str().cyan(),
+        user.mid,
+        vip
+    );
+
+    if let Some(expires_at) = cookie.sessdata_expires_at() {
+        let days = days_until(expires_at);
+        if days < EXPIRY_WARNING_DAYS {
+            println!(
+                "{}",
+                format!("登录将在 {} 天内过期, 请及时使用 login 重新登录", days + 1).yellow()
+            );
+        }
+    }
+}
+
+/**
+ * 输出漫画大会员状态.
+ */
+pub fn print_vip_info(vip: &VipInfo) {
+    if !vip.is_vip() {
+        println!("漫画大会员: 未开通");
+        return;
+    }
+    let vip_name = if vip.vip_type == 2 {
+        "年度大会员"
+    } else {
+        "大会员"
+    };
+    println!(
+        "漫画大会员: {} 剩余 {} 天",
+        vip_name.cyan(),
+        days_until(vip.vip_due_date / 1000)
+    );
+}
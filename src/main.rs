@@ -1,72 +1,241 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
-    io::{self},
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 
+use accounts::{
+    active_profile, check_profile_name, get_current_profile, get_profile_config_path,
+    init_active_profile, list_profiles, profile_exists, set_current_profile, DEFAULT_PROFILE,
+};
 use apis::{
-    do_download_tasks, get_manga_detail, get_userinfo, search_manga, Episode, SearchMangaItem,
+    do_download_tasks, get_coupons, get_episode_filename, get_manga_detail, get_userinfo,
+    get_vip_info, get_wallet, list_bought_comics, list_bought_episode_ids, list_favorites, logout,
+    search_manga, DownloadOptions, Episode, LockedPolicy, SearchMangaItem, UserInfo, UserInfoError,
 };
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use config::{CdnFormat, ImageQuality};
+use cookie::CookieJar;
+use cookie_import::import_cookie_file;
+use credentials::PASSPHRASE_ENV;
 use crossterm::{
-    cursor::MoveTo,
-    event::{self, KeyCode, KeyEvent},
+    cursor::{MoveTo, Show},
+    event::{self, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     style::Stylize,
     terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
 };
-use dialoguer::{theme::ColorfulTheme, Input, MultiSelect, Select};
-use dirs::config_dir;
+use dedupe::{dhash, format_hash, BlockedPage};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
+use http::{init_clients, ProxyOptions};
 use indicatif::ProgressBar;
-use tokio;
-use utils::{create_desc_dir, get_safe_filename, path_exists};
+use login::{print_session, print_vip_info, qrcode_login};
+use process::{parse_page_aspect, ProcessOptions, SpreadMode, StripMode};
+use profile::{find_profile, AutoCrop};
+use resume::ResumeState;
+use tokio_util::sync::CancellationToken;
+use transcode::{TranscodeFormat, TranscodeOptions};
+use utils::{
+    create_desc_dir, get_safe_filename, parse_rate, path_exists, remove_stale_part_files,
+    wait_for_shutdown_signal,
+};
+use verify::verify_dir;
 
+mod accounts;
 mod apis;
+mod comicinfo;
 mod config;
+mod cookie;
+mod cookie_import;
+mod credentials;
+mod dedupe;
+mod http;
+mod imaging;
+mod login;
+mod manifest;
+mod process;
+mod profile;
+mod resume;
+mod transcode;
 mod utils;
+mod verify;
+
+/**
+ * 按配置文件与命令行的代理、超时设置初始化 Client.
+ */
+fn init_config_clients(config: &config::Config, proxy: ProxyOptions, max_rate: Option<u64>) {
+    if let Err(e) = config.timeout.validate() {
+        println!("{}", e.red());
+        std::process::exit(1);
+    }
+
+    // 命令行指定的代理优先于配置文件.
+    let proxy = ProxyOptions {
+        api_proxy: proxy.api_proxy.or_else(|| config.api_proxy.clone()),
+        image_proxy: proxy.image_proxy.or_else(|| config.image_proxy.clone()),
+    };
+    if let Err(e) = init_clients(&proxy, &config.timeout, max_rate) {
+        println!("{}", format!("代理设置无效: {}", e).red());
+        std::process::exit(1);
+    }
+}
+
+/**
+ * Cookie 无效时让用户选择扫码登录或手动输入 Cookie.
+ */
+async fn prompt_login() -> CookieJar {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Cookie 无效, 请选择登录方式:")
+        .items(&["扫码登录", "手动输入 Cookie"])
+        .default(0)
+        .interact()
+        .unwrap();
 
-async fn load_user_config(download_path: Option<String>) -> config::Config {
-    let mut config_path = config_dir().unwrap_or_else(|| PathBuf::from("."));
-    config_path.push("bili_manga_downloader");
-    fs::create_dir_all(&config_path).expect("创建配置文件夹失败");
-    config_path.push("config.json");
+    if selection == 0 {
+        match qrcode_login().await {
+            Ok(cookie) => return cookie,
+            Err(e) => println!("{}", format!("扫码登录失败: {}", e).red()),
+        }
+    }
 
+    let cookie: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("请输入 Bilibili Cookie(完整 Cookie 或 SESSDATA):")
+        .interact_text()
+        .unwrap();
+    CookieJar::parse(&cookie)
+}
+
+/**
+ * 退出登录并清除保存的 Cookie.
+ */
+async fn run_logout(args: &Args) {
+    let config_path = get_config_path();
     let mut config = config::Config::load(&config_path);
+    init_config_clients(&config, get_proxy_options(args), None);
 
-    if download_path.is_some() {
-        config.download_path = download_path.unwrap();
+    if let Err(e) = logout(&config.cookie).await {
+        println!("{}", format!("退出登录失败: {}", e).red());
+        return;
     }
+    config.cookie = CookieJar::default();
+    config.save(&config_path);
+    println!("{}", "已退出登录".green());
+}
 
-    loop {
-        // 校验 cookie
-        let is_valid_cookie = get_userinfo(&config.cookie).await;
-        if !is_valid_cookie {
-            let cookie: String = Input::with_theme(&ColorfulTheme::default())
-                .with_prompt("Cookie 无效, 请输入 Bilibili Cookie(SESSDATA 里):")
-                .interact_text()
-                .unwrap();
+/**
+ * 保存接口响应中更新过的 Cookie.
+ */
+fn save_changed_cookies(config: &config::Config) {
+    if config.cookie.take_changed() {
+        config.save(&get_config_path());
+    }
+}
+
+/**
+ * 扫码或从浏览器导出的文件登录, 并保存 Cookie.
+ */
+async fn run_login(args: &Args, from_file: Option<String>) {
+    let config_path = get_config_path();
+    let mut config = config::Config::load(&config_path);
+    init_config_clients(&config, get_proxy_options(args), None);
 
-            config.cookie = cookie;
+    let cookie = match from_file {
+        Some(path) => import_cookie_file(Path::new(&path)),
+        None => qrcode_login().await,
+    };
+    let cookie = match cookie {
+        Ok(cookie) => cookie,
+        Err(e) => {
+            println!("{}", format!("登录失败: {}", e).red());
+            return;
         }
-        break;
-    }
+    };
+    let user = match get_userinfo(&cookie).await {
+        Ok(user) => user,
+        Err(e) => {
+            println!("{}", format!("登录失败: {}", e).red());
+            return;
+        }
+    };
 
-    loop {
-        // 校验下载路径
-        let is_valid_download_path = path_exists(&config.download_path);
-        if !is_valid_download_path {
-            let download_path: String = Input::with_theme(&ColorfulTheme::default())
-                .with_prompt("下载路径不存在, 请输入:")
-                .interact_text()
-                .unwrap();
+    println!("{}", "登录成功".green());
+    print_session(&user, &cookie);
+    config.cookie = cookie;
+    config.save(&config_path);
+}
+
+fn get_config_path() -> PathBuf {
+    get_profile_config_path(active_profile())
+}
 
-            config.download_path = download_path;
+/**
+ * 校验 Cookie 并输出当前登录的用户, Cookie 无效时让用户重新登录.
+ */
+async fn ensure_login(config: &mut config::Config) -> UserInfo {
+    // 校验 cookie, 网络错误时直接退出, 非交互运行时无法登录, 也直接退出.
+    let user = loop {
+        match get_userinfo(&config.cookie).await {
+            Ok(user) => break user,
+            Err(UserInfoError::NotLoggedIn) => {}
+            Err(e) => {
+                println!("{}", e.to_string().red());
+                std::process::exit(1);
+            }
         }
-        break;
+        if !std::io::stdin().is_terminal() {
+            let profile_arg = if active_profile() == DEFAULT_PROFILE {
+                String::new()
+            } else {
+                format!("--profile {} ", active_profile())
+            };
+            println!(
+                "{}",
+                format!(
+                    "登录已失效, 需要重新登录: bili-manga-downloader {}login",
+                    profile_arg
+                )
+                .red()
+            );
+            std::process::exit(1);
+        }
+        config.cookie = prompt_login().await;
+    };
+    print_session(&user, &config.cookie);
+    user
+}
+
+async fn load_user_config(
+    download_path: Option<String>,
+    proxy: ProxyOptions,
+    max_rate: Option<u64>,
+) -> config::Config {
+    let config_path = get_config_path();
+
+    let mut config = config::Config::load(&config_path);
+
+    if let Some(download_path) = download_path {
+        config.download_path = download_path;
     }
 
+    init_config_clients(&config, proxy, max_rate);
+
+    ensure_login(&mut config).await;
+
+    // 校验下载路径
+    let is_valid_download_path = path_exists(&config.download_path);
+    if !is_valid_download_path {
+        let download_path: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("下载路径不存在, 请输入:")
+            .interact_text()
+            .unwrap();
+
+        config.download_path = download_path;
+    }
+
+    config.cookie.take_changed();
     config.save(&config_path);
 
     config
@@ -76,15 +245,13 @@ async fn load_user_config(download_path: Option<String>) -> config::Config {
  * 通过用户输入的漫画名获取漫画信息.
  */
 async fn get_selected_manga(manga_name: Option<String>) -> Option<SearchMangaItem> {
-    let input: String;
-    if manga_name.is_none() {
-        input = Input::<String>::with_theme(&ColorfulTheme::default())
+    let input = match manga_name {
+        Some(manga_name) => manga_name,
+        None => Input::<String>::with_theme(&ColorfulTheme::default())
             .with_prompt("输入漫画名称:")
             .interact_text()
-            .unwrap();
-    } else {
-        input = manga_name.unwrap();
-    }
+            .unwrap(),
+    };
 
     let input = input.trim();
     let pb = ProgressBar::new_spinner();
@@ -120,7 +287,11 @@ async fn get_selected_manga(manga_name: Option<String>) -> Option<SearchMangaIte
     Some(selected_manga.clone())
 }
 
-async fn get_episode_pages(manga_id: &u32, cookie: &str) -> Option<Vec<Vec<Episode>>> {
+async fn get_episode_pages(
+    manga_id: &u32,
+    cookie: &CookieJar,
+    unlocked_only: bool,
+) -> Option<Vec<Vec<Episode>>> {
     let pb = ProgressBar::new_spinner();
     pb.enable_steady_tick(Duration::from_millis(100));
     let manga_detail = get_manga_detail(manga_id, cookie).await;
@@ -135,7 +306,11 @@ async fn get_episode_pages(manga_id: &u32, cookie: &str) -> Option<Vec<Vec<Episo
 
     let mut episode_pages: Vec<Vec<Episode>> = Vec::new();
     let mut current_page: Vec<Episode> = Vec::new();
-    for (index, episode) in manga_detail.ep_list.iter().enumerate() {
+    let episodes = manga_detail
+        .ep_list
+        .iter()
+        .filter(|episode| !(unlocked_only && episode.is_locked));
+    for (index, episode) in episodes.enumerate() {
         current_page.push(episode.clone());
 
         if index % 10 == 0 && index != 0 {
@@ -148,16 +323,29 @@ async fn get_episode_pages(manga_id: &u32, cookie: &str) -> Option<Vec<Vec<Episo
         episode_pages.push(current_page);
     }
 
+    if episode_pages.is_empty() {
+        let message = if unlocked_only {
+            "没有已解锁的章节"
+        } else {
+            "没有可下载的章节"
+        };
+        println!("{}", message.yellow());
+        return None;
+    }
+
     Some(episode_pages)
 }
 
 /**
  * 获取用户选择的章节信息.
  */
-fn get_selected_episodes(episode_pages: &Vec<Vec<String>>) -> HashMap<String, Vec<usize>> {
+fn get_selected_episodes(episode_pages: &[Vec<String>]) -> HashMap<String, Vec<usize>> {
     let mut current_page = 0;
 
     let mut select_episode_map: HashMap<String, Vec<usize>> = HashMap::new();
+    if episode_pages.is_empty() {
+        return select_episode_map;
+    }
 
     let mut stdout = io::stdout();
 
@@ -167,11 +355,6 @@ fn get_selected_episodes(episode_pages: &Vec<Vec<String>>) -> HashMap<String, Ve
     }
 
     loop {
-        if let Err(e) = event::read() {
-            eprintln!("Failed to read event: {:?}", e);
-            break;
-        }
-
         execute!(stdout, MoveTo(0, 0)).unwrap();
         execute!(stdout, Clear(ClearType::All)).unwrap();
 
@@ -185,24 +368,40 @@ fn get_selected_episodes(episode_pages: &Vec<Vec<String>>) -> HashMap<String, Ve
         let mut default_select = vec![];
         for (index, item) in episode_pages[current_page].iter().enumerate() {
             let selected_episodes = select_episode_map.get(&current_page.to_string());
-            if selected_episodes.is_none() {
-                println!("{}", item);
-                default_select.push(false);
-            } else {
-                let selected_episodes = selected_episodes.unwrap();
-                if selected_episodes.contains(&index) {
+            match selected_episodes {
+                Some(selected_episodes) if selected_episodes.contains(&index) => {
                     println!("{} {}", item, "\u{2713}".green());
                     default_select.push(true);
-                } else {
+                }
+                _ => {
                     println!("{}", item);
                     default_select.push(false);
                 }
             }
         }
 
-        let event = event::read().unwrap();
-        if let event::Event::Key(KeyEvent { code, .. }) = event {
+        // 处理每一次按键后重新绘制, 不丢弃任何按键.
+        let event = match event::read() {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Failed to read event: {:?}", e);
+                break;
+            }
+        };
+        // Windows 下按下与松开都会产生事件, 只处理按下.
+        if let event::Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        {
             match code {
+                // raw mode 下 Ctrl-C 不会产生 SIGINT, 需要手动恢复终端后退出选择.
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    select_episode_map.clear();
+                    break;
+                }
                 KeyCode::Up => {
                     if current_page > 0 {
                         current_page -= 1;
@@ -255,38 +454,40 @@ fn get_selected_episodes(episode_pages: &Vec<Vec<String>>) -> HashMap<String, Ve
     select_episode_map
 }
 
-#[derive(Parser, Debug)]
-#[command(version, about, long_about=None, author)]
-struct Args {
-    #[arg(short, long)]
-    manga_name: Option<String>,
-    #[arg(short, long)]
-    download_path: Option<String>,
-}
-
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
-
-    let config = load_user_config(args.download_path).await;
-
-    println!("漫画下载目录: {}", config.download_path.clone().cyan());
-
-    let selected_manga = get_selected_manga(args.manga_name).await;
-
-    if selected_manga.is_none() {
-        return;
+/**
+ * 上次下载被取消时, 询问是否继续下载剩余章节.
+ */
+fn get_resume_episodes(dest_path: &Path, manga_id: u32) -> Option<Vec<Episode>> {
+    let state = ResumeState::load(dest_path)?;
+    if state.manga_id != manga_id || state.episodes.is_empty() {
+        return None;
     }
 
-    let selected_manga = selected_manga.unwrap();
-
-    let episode_pages = get_episode_pages(&selected_manga.id, &config.cookie).await;
-
-    if episode_pages.is_none() {
-        return;
+    let resume = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "上次有 {} 个章节未下载完成, 是否继续下载?",
+            state.episodes.len()
+        ))
+        .default(true)
+        .interact()
+        .unwrap();
+    if !resume {
+        ResumeState::remove(dest_path);
+        return None;
     }
 
-    let episode_pages = episode_pages.unwrap();
+    Some(state.episodes)
+}
+
+/**
+ * 获取章节列表并由用户选择需要下载的章节.
+ */
+async fn get_download_episodes(
+    manga_id: &u32,
+    cookie: &CookieJar,
+    unlocked_only: bool,
+) -> Option<Vec<Episode>> {
+    let episode_pages = get_episode_pages(manga_id, cookie, unlocked_only).await?;
     let episode_pages_selections = episode_pages
         .iter()
         .map(|page| {
@@ -313,10 +514,6 @@ async fn main() {
 
     let selected_episodes = get_selected_episodes(&episode_pages_selections);
 
-    if selected_episodes.is_empty() {
-        return;
-    }
-
     let mut download_episodes: Vec<Episode> = Vec::new();
     for (page, ep_indexes) in selected_episodes.iter() {
         let episodes = &episode_pages[page.parse::<usize>().unwrap()];
@@ -327,29 +524,900 @@ async fn main() {
     }
 
     if download_episodes.is_empty() {
+        return None;
+    }
+
+    Some(download_episodes)
+}
+
+fn get_download_options(args: &Args, config: &config::Config) -> DownloadOptions {
+    let profile = args.device.as_ref().map(|name| {
+        find_profile(name, &config.device_profiles).unwrap_or_else(|e| {
+            println!("{}", e.red());
+            std::process::exit(1);
+        })
+    });
+
+    // 默认按设备屏幕的高宽比切分或拼接.
+    let aspect = args.page_aspect.unwrap_or_else(|| match &profile {
+        Some(profile) => profile.height as f32 / profile.width as f32,
+        None => 1.5,
+    });
+    let strip = if args.stitch {
+        Some(StripMode::Stitch { aspect })
+    } else if args.split_tall {
+        Some(StripMode::Split { aspect })
+    } else {
+        None
+    };
+
+    // 命令行设置优先于设备配置.
+    let auto_crop = if args.auto_crop {
+        Some(AutoCrop {
+            tolerance: args.crop_tolerance,
+            min_content: args.crop_min_content,
+        })
+    } else {
+        profile.as_ref().and_then(|profile| profile.auto_crop)
+    };
+
+    let options = DownloadOptions {
+        max_rate: args.max_rate,
+        stall_timeout: Duration::from_secs(config.timeout.stall),
+        retries: config.timeout.retries,
+        decode_check: args.decode_check,
+        image_quality: if args.original {
+            ImageQuality::default()
+        } else {
+            ImageQuality {
+                max_width: args.max_width.or(config.image_quality.max_width),
+                format: args.image_format.or(config.image_quality.format),
+            }
+        },
+        block_list: config.block_list.clone(),
+        drop_duplicates: args.drop_duplicates,
+        process: ProcessOptions {
+            strip,
+            spread: args.spread,
+            auto_crop,
+            profile,
+        },
+        transcode: args
+            .transcode
+            .map(|format| get_transcode_options(args, format)),
+        locked_policy: args.locked,
+    };
+
+    // 没有启用 AVIF 解码器, 需要解码图片的处理无法生效.
+    if options.image_quality.format == Some(CdnFormat::Avif) {
+        if !options.process.is_empty() || options.transcode.is_some() {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "AVIF 图片无法解码, 不能按设备处理、切分拼接、处理跨页、裁白边或转码, 请使用 --image-format jpg 或 webp",
+                )
+                .exit();
+        }
+        if !options.block_list.is_empty() {
+            println!("{}", "AVIF 图片无法解码, 本次下载不会删除屏蔽图片".yellow());
+        }
+    }
+    options
+}
+
+/**
+ * 转码设置, 无损格式提醒通常不会替换原图.
+ */
+fn get_transcode_options(args: &Args, format: TranscodeFormat) -> TranscodeOptions {
+    let name = format.to_possible_value().unwrap().get_name().to_string();
+    if format.is_lossless() && !args.transcode_always {
+        println!(
+            "{}",
+            format!(
+                "{} 为无损格式, 通常比原图更大, 只有转码后更小的页面才会替换原图",
+                name
+            )
+            .yellow()
+        );
+    }
+    TranscodeOptions {
+        format,
+        quality: args.transcode_quality.unwrap_or(80),
+        only_if_smaller: !args.transcode_always,
+    }
+}
+
+/// 是否已开始下载, 之前收到退出信号时直接退出.
+static DOWNLOAD_STARTED: AtomicBool = AtomicBool::new(false);
+
+/**
+ * 第一次 Ctrl-C 取消下载, 第二次强制退出.
+ */
+fn spawn_shutdown_listener() -> CancellationToken {
+    let cancel_token = CancellationToken::new();
+    let signal_token = cancel_token.clone();
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        // 开始下载前(如选择章节时)直接恢复终端并退出.
+        if !DOWNLOAD_STARTED.load(Ordering::SeqCst) {
+            restore_terminal();
+            std::process::exit(130);
+        }
+        eprintln!("{}", "正在取消下载, 再次按 Ctrl-C 强制退出".yellow());
+        signal_token.cancel();
+        wait_for_shutdown_signal().await;
+        std::process::exit(130);
+    });
+    cancel_token
+}
+
+/**
+ * 恢复选择章节时修改的终端状态.
+ */
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), Show);
+}
+
+/**
+ * 下载章节到漫画目录, 记录未完成的章节并输出下载结果.
+ */
+async fn download_episodes_to(
+    manga_id: u32,
+    episodes: Vec<Episode>,
+    cookie: &CookieJar,
+    dest_path: &PathBuf,
+    options: &DownloadOptions,
+    cancel_token: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    DOWNLOAD_STARTED.store(true, Ordering::SeqCst);
+
+    // 创建下载目录
+    create_desc_dir(dest_path.to_str().unwrap());
+    let stale_count = remove_stale_part_files(dest_path);
+    if stale_count > 0 {
+        println!("已清理 {} 个未写完的临时文件", stale_count);
+    }
+
+    let report =
+        do_download_tasks(manga_id, episodes, cookie, dest_path, options, cancel_token).await?;
+
+    // 取消与失败的章节都记录下来, 下次选择该漫画时继续下载.
+    let mut unfinished = report.cancelled.clone();
+    unfinished.extend(report.failed_episodes.iter().cloned());
+    unfinished.sort_by(|a, b| a.ord.total_cmp(&b.ord));
+    if unfinished.is_empty() {
+        ResumeState::remove(dest_path);
+    } else {
+        let state = ResumeState {
+            manga_id,
+            episodes: unfinished,
+        };
+        if let Err(e) = state.save(dest_path) {
+            println!("{}", format!("下载进度保存失败: {}", e).red());
+        }
+    }
+
+    if !report.cancelled.is_empty() {
+        println!("{}", "下载已取消, 已完成的章节:".yellow());
+        for filename in &report.completed {
+            println!("{}", filename.as_str().green());
+        }
+        println!(
+            "{}",
+            format!(
+                "{} 个章节未下载, 下次选择该漫画时可以继续下载",
+                report.cancelled.len()
+            )
+            .yellow()
+        );
+    }
+
+    for message in &report.failed {
+        println!("{}", message.as_str().red());
+    }
+    if !report.failed_episodes.is_empty() {
+        println!(
+            "{}",
+            format!(
+                "{} 个章节下载失败, 下次选择该漫画时可以重新下载",
+                report.failed_episodes.len()
+            )
+            .yellow()
+        );
+    }
+
+    if !report.skipped.is_empty() {
+        println!("{}", "已跳过的章节:".yellow());
+        for message in &report.skipped {
+            println!("{}", message.as_str().yellow());
+        }
+    }
+
+    if !report.locked.is_empty() {
+        println!("{}", "未解锁的章节:".yellow());
+        for message in &report.locked {
+            println!("{}", message.as_str().yellow());
+        }
+    }
+
+    if let Some(summary) = &report.transcode_summary {
+        println!("{}", summary.as_str().cyan());
+    }
+
+    if report.failed.is_empty() && report.cancelled.is_empty() && !report.completed.is_empty() {
+        println!("{}", "所有章节下载完成".green());
+    }
+    Ok(())
+}
+
+/**
+ * 校验下载目录中的压缩文件, 可选重新下载校验失败的章节.
+ */
+async fn run_verify(args: &Args, path: Option<String>, redownload: bool) {
+    let root = match path {
+        Some(path) => PathBuf::from(path),
+        // 只需要下载目录, 不解密 Cookie, 无人值守时不需要口令.
+        None => PathBuf::from(config::Config::read(&get_config_path()).download_path),
+    };
+    if !root.exists() {
+        println!("{}", format!("目录不存在: {}", root.display()).red());
+        return;
+    }
+
+    println!("校验目录: {}", root.display().to_string().cyan());
+    let report = verify_dir(&root);
+
+    for broken in &report.broken {
+        println!("{}", broken.archive_path.display().to_string().red());
+        for problem in &broken.problems {
+            println!("  - {}", problem);
+        }
+    }
+    for archive_path in &report.unverified {
+        println!(
+            "{} {}",
+            archive_path.display().to_string().yellow(),
+            "(没有校验清单)".yellow()
+        );
+    }
+    println!(
+        "校验通过 {}, 校验失败 {}, 无法校验 {}",
+        report.ok.to_string().green(),
+        report.broken.len().to_string().red(),
+        report.unverified.len().to_string().yellow()
+    );
+
+    if !redownload || report.broken.is_empty() {
+        return;
+    }
+
+    let config = load_user_config(
+        args.download_path.clone(),
+        get_proxy_options(args),
+        args.max_rate,
+    )
+    .await;
+    let options = get_download_options(args, &config);
+    let cancel_token = spawn_shutdown_listener();
+
+    // 按漫画目录分组后重新下载.
+    let mut groups: BTreeMap<PathBuf, (u32, Vec<Episode>)> = BTreeMap::new();
+    for broken in report.broken {
+        let dest_path = broken.archive_path.parent().unwrap().to_path_buf();
+        groups
+            .entry(dest_path)
+            .or_insert_with(|| (broken.manifest.manga_id, vec![]))
+            .1
+            .push(broken.manifest.episode);
+    }
+    let mut has_error = false;
+    for (dest_path, (manga_id, episodes)) in groups {
+        if cancel_token.is_cancelled() {
+            break;
+        }
+        println!("重新下载: {}", dest_path.display().to_string().cyan());
+        if let Err(e) = download_episodes_to(
+            manga_id,
+            episodes,
+            &config.cookie,
+            &dest_path,
+            &options,
+            &cancel_token,
+        )
+        .await
+        {
+            println!("{}", e.to_string().red());
+            has_error = true;
+        }
+    }
+    save_changed_cookies(&config);
+    if has_error {
+        std::process::exit(1);
+    }
+}
+
+/**
+ * 管理账号, 每个账号有独立的 Cookie、下载目录与图片设置.
+ */
+fn run_profile(action: ProfileAction) {
+    match action {
+        ProfileAction::List => {
+            let current = get_current_profile();
+            for name in list_profiles() {
+                let config = config::Config::read(&get_profile_config_path(&name));
+                let marker = if name == current { "*" } else { " " };
+                let status = if config.encrypted_cookie.is_some() {
+                    "已登录(已加密)".green()
+                } else if config.cookie.is_empty() {
+                    "未登录".yellow()
+                } else {
+                    "已登录".green()
+                };
+                println!(
+                    "{} {} {} {}",
+                    marker,
+                    name.as_str().cyan(),
+                    status,
+                    config.download_path
+                );
+            }
+        }
+        ProfileAction::Add { name } => {
+            if let Err(e) = check_profile_name(&name) {
+                println!("{}", e.red());
+                return;
+            }
+            if profile_exists(&name) {
+                println!("{}", format!("账号已存在: {}", name).red());
+                return;
+            }
+            let config = config::Config {
+                cookie: CookieJar::default(),
+                encrypted_cookie: None,
+                encrypt_cookie: false,
+                download_path: "".to_string(),
+                ..config::Config::read(&get_config_path())
+            };
+            let config_path = get_profile_config_path(&name);
+            fs::create_dir_all(config_path.parent().unwrap()).expect("创建配置文件夹失败");
+            config.save(&config_path);
+            println!(
+                "已添加账号 {}, 使用 --profile {} login 登录",
+                name.as_str().green(),
+                name
+            );
+        }
+        ProfileAction::Remove { name } => {
+            if name == DEFAULT_PROFILE {
+                println!("{}", "默认账号不能删除".red());
+                return;
+            }
+            if check_profile_name(&name).is_err() || !profile_exists(&name) {
+                println!("{}", format!("账号不存在: {}", name).red());
+                return;
+            }
+            let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("确定删除账号 {} 及其保存的 Cookie?", name))
+                .default(false)
+                .interact()
+                .unwrap();
+            if !confirmed {
+                return;
+            }
+            if get_current_profile() == name {
+                set_current_profile(DEFAULT_PROFILE);
+            }
+            fs::remove_file(get_profile_config_path(&name)).expect("删除账号失败");
+            println!("已删除账号 {}", name);
+        }
+        ProfileAction::Switch { name } => {
+            if check_profile_name(&name).is_err() || !profile_exists(&name) {
+                println!("{}", format!("账号不存在: {}", name).red());
+                return;
+            }
+            set_current_profile(&name);
+            println!("已切换到账号 {}", name.as_str().green());
+        }
+    }
+}
+
+/**
+ * 使用口令加密保存的 Cookie, 或恢复明文保存.
+ */
+fn run_encrypt(disable: bool) {
+    let config_path = get_config_path();
+    let mut config = config::Config::load(&config_path);
+
+    if disable {
+        if !config.encrypt_cookie {
+            println!("Cookie 未加密");
+            return;
+        }
+        config.encrypt_cookie = false;
+        config.encrypted_cookie = None;
+        config.save(&config_path);
+        println!("{}", "已取消加密, Cookie 将明文保存".yellow());
         return;
     }
 
+    if config.encrypt_cookie {
+        println!("Cookie 已加密");
+        return;
+    }
+    config.encrypt_cookie = true;
+    config.save(&config_path);
+    println!(
+        "{}",
+        format!(
+            "Cookie 已加密, 无人值守运行时可以通过环境变量 {} 提供口令",
+            PASSPHRASE_ENV
+        )
+        .green()
+    );
+}
+
+/**
+ * 显示账号的大会员状态、漫读券与漫币余额.
+ */
+async fn run_account(args: &Args) {
+    let config_path = get_config_path();
+    let mut config = config::Config::load(&config_path);
+    init_config_clients(&config, get_proxy_options(args), None);
+    ensure_login(&mut config).await;
+
+    match get_vip_info(&config.cookie).await {
+        Ok(vip) => print_vip_info(&vip),
+        Err(e) => println!("{}", format!("大会员信息获取失败: {}", e).red()),
+    }
+
+    match get_wallet(&config.cookie).await {
+        Ok(wallet) => {
+            println!("漫读券: {}", wallet.remain_coupon.to_string().cyan());
+            println!("漫币: {}", wallet.remain_gold.to_string().cyan());
+        }
+        Err(e) => println!("{}", format!("钱包获取失败: {}", e).red()),
+    }
+
+    match get_coupons(&config.cookie).await {
+        Ok(coupons) => {
+            println!(
+                "未过期漫读券: {}",
+                coupons.total_remain_amount.to_string().cyan()
+            );
+            for coupon in &coupons.user_coupons {
+                println!(
+                    "  - {} x{}, {} 过期",
+                    coupon.type_name, coupon.remain_amount, coupon.expire_time
+                );
+            }
+        }
+        Err(e) => println!("{}", format!("漫读券获取失败: {}", e).red()),
+    }
+
+    config.cookie.take_changed();
+    config.save(&config_path);
+}
+
+/**
+ * 管理屏蔽图片.
+ */
+fn run_block(paths: Vec<String>, list: bool, clear: bool) {
+    let config_path = get_config_path();
+    // 只修改屏蔽列表, 不解密 Cookie, 加密的 Cookie 会原样保存.
+    let mut config = config::Config::read(&config_path);
+
+    if clear {
+        config.block_list.pages.clear();
+        println!("已清空屏蔽图片");
+    }
+
+    for path in &paths {
+        let image = match image::open(path) {
+            Ok(image) => image,
+            Err(e) => {
+                println!("{}", format!("{} 读取失败: {}", path, e).red());
+                continue;
+            }
+        };
+        let hash = format_hash(dhash(&image));
+        if config.block_list.pages.iter().any(|page| page.hash == hash) {
+            println!("{} 已经屏蔽过", path);
+            continue;
+        }
+        let name = Path::new(path)
+            .file_name()
+            .map_or(path.clone(), |name| name.to_string_lossy().to_string());
+        println!("已屏蔽 {} ({})", name.as_str().green(), hash);
+        config.block_list.pages.push(BlockedPage { hash, name });
+    }
+
+    if list {
+        if config.block_list.is_empty() {
+            println!("没有屏蔽图片");
+        }
+        for page in &config.block_list.pages {
+            println!("{} {}", page.hash, page.name);
+        }
+    }
+
+    config.save(&config_path);
+}
+
+fn get_proxy_options(args: &Args) -> ProxyOptions {
+    ProxyOptions {
+        api_proxy: args.api_proxy.clone().or_else(|| args.proxy.clone()),
+        image_proxy: args.image_proxy.clone().or_else(|| args.proxy.clone()),
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about=None, author)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// 使用的账号, 不设置时使用 profile switch 切换的账号
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[arg(short, long)]
+    manga_name: Option<String>,
+    #[arg(short, long)]
+    download_path: Option<String>,
+    /// 选择章节时只列出已解锁的章节
+    #[arg(long)]
+    unlocked_only: bool,
+    /// 未解锁章节的处理方式: skip 跳过, fail 不下载任何章节并退出, preview 下载试看页面
+    #[arg(long, value_enum, default_value_t = LockedPolicy::Skip)]
+    locked: LockedPolicy,
+    /// 下载书架中所有漫画里未下载过的已解锁章节
+    #[arg(long, conflicts_with = "manga_name")]
+    bookshelf: bool,
+    /// 下载账号购买过的所有章节
+    #[arg(long, conflicts_with_all = ["manga_name", "bookshelf"])]
+    purchased: bool,
+    /// 限制所有图片下载的总带宽, 如 2MiB/s、500KB/s
+    #[arg(long, value_parser = parse_rate)]
+    max_rate: Option<u64>,
+    /// 完整解码每张图片以发现损坏的数据, 会占用更多 CPU
+    #[arg(long)]
+    decode_check: bool,
+    /// 从 CDN 请求的图片最大宽度, 如 1100、1600, 不设置时下载原图
+    #[arg(long)]
+    max_width: Option<u32>,
+    /// 从 CDN 请求的图片格式
+    #[arg(long, value_enum)]
+    image_format: Option<CdnFormat>,
+    /// 下载原图, 忽略配置文件中的图片宽度与格式
+    #[arg(long, conflicts_with_all = ["max_width", "image_format"])]
+    original: bool,
+    /// 按阅读设备处理图片, 如 kindle-paperwhite、kobo-libra2, 也可以使用配置文件中的自定义配置
+    #[arg(long)]
+    device: Option<String>,
+    /// 删除同一部漫画中已经下载过的完全相同的页面
+    #[arg(long)]
+    drop_duplicates: bool,
+    /// 在空白处将条漫的长图切分为多页
+    #[arg(long, group = "strip", conflicts_with = "stitch")]
+    split_tall: bool,
+    /// 切分后每页的高宽比, 拼接时只拼接低于该高度的短图, 默认使用设备屏幕的高宽比或 1.5
+    #[arg(long, requires = "strip", value_parser = parse_page_aspect)]
+    page_aspect: Option<f32>,
+    /// 将宽度相同、高度不足一页的连续短图拼接为一张长图
+    #[arg(long, group = "strip")]
+    stitch: bool,
+    /// 横向跨页的处理方式, 不设置时保持原样
+    #[arg(long, value_enum)]
+    spread: Option<SpreadMode>,
+    /// 自动裁掉页面四周的空白
+    #[arg(long)]
+    auto_crop: bool,
+    /// 灰度值与纯白相差不超过该值的像素视为空白
+    #[arg(long, default_value_t = 16, requires = "auto_crop")]
+    crop_tolerance: u8,
+    /// 裁剪后的宽高至少保留原图的比例, 否则跳过该页
+    #[arg(long, default_value_t = 0.5, requires = "auto_crop")]
+    crop_min_content: f32,
+    /// 写入压缩文件前将图片转码为指定格式
+    #[arg(long, value_enum)]
+    transcode: Option<TranscodeFormat>,
+    /// 有损转码(jpeg、avif)的质量, 1-100, 默认 80
+    #[arg(long, requires = "transcode", value_parser = clap::value_parser!(u8).range(1..=100))]
+    transcode_quality: Option<u8>,
+    /// 即使转码后更大也替换原图
+    #[arg(long)]
+    transcode_always: bool,
+    /// 所有请求使用的代理, 如 http://127.0.0.1:7890、socks5://127.0.0.1:1080
+    #[arg(long)]
+    proxy: Option<String>,
+    /// 访问哔哩哔哩接口使用的代理, 优先于 --proxy
+    #[arg(long)]
+    api_proxy: Option<String>,
+    /// 下载图片使用的代理, 优先于 --proxy
+    #[arg(long)]
+    image_proxy: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ProfileAction {
+    /// 列出所有账号
+    List,
+    /// 添加账号, 沿用当前账号的下载与图片设置
+    Add { name: String },
+    /// 删除账号
+    Remove { name: String },
+    /// 切换默认使用的账号
+    Switch { name: String },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// 校验下载目录中的压缩文件
+    Verify {
+        /// 需要校验的目录, 默认为下载目录
+        path: Option<String>,
+        /// 重新下载校验失败的章节
+        #[arg(long)]
+        redownload: bool,
+    },
+    /// 扫码登录哔哩哔哩账号
+    Login {
+        /// 从浏览器导出的 cookies.txt、Firefox 的 cookies.sqlite 或 Chromium 的配置目录导入 Cookie
+        #[arg(long)]
+        from_file: Option<String>,
+    },
+    /// 退出登录并清除保存的 Cookie
+    Logout,
+    /// 显示大会员状态、漫读券与漫币余额
+    Account,
+    /// 管理账号
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// 使用口令加密保存的 Cookie
+    Encrypt {
+        /// 取消加密, 恢复明文保存
+        #[arg(long)]
+        disable: bool,
+    },
+    /// 登记屏蔽图片, 下载时删除与之相似的页面
+    Block {
+        /// 需要屏蔽的图片文件
+        paths: Vec<String>,
+        /// 列出已屏蔽的图片
+        #[arg(long)]
+        list: bool,
+        /// 清空已屏蔽的图片
+        #[arg(long)]
+        clear: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    // 无损格式不使用转码质量, 避免设置后被静默忽略.
+    if let Some(format) = args.transcode.filter(TranscodeFormat::is_lossless) {
+        if args.transcode_quality.is_some() {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!(
+                        "{} 为无损格式, 不支持 --transcode-quality",
+                        format.to_possible_value().unwrap().get_name()
+                    ),
+                )
+                .exit();
+        }
+    }
+
+    if let Err(e) = init_active_profile(args.profile.clone()) {
+        println!("{}", e.red());
+        return;
+    }
+
+    match args.command.clone() {
+        Some(Command::Verify { path, redownload }) => run_verify(&args, path, redownload).await,
+        Some(Command::Login { from_file }) => run_login(&args, from_file).await,
+        Some(Command::Logout) => run_logout(&args).await,
+        Some(Command::Account) => run_account(&args).await,
+        Some(Command::Profile { action }) => run_profile(action),
+        Some(Command::Encrypt { disable }) => run_encrypt(disable),
+        Some(Command::Block { paths, list, clear }) => run_block(paths, list, clear),
+        None => run_download(args).await,
+    }
+}
+
+/**
+ * 批量下载时选择的章节.
+ */
+enum ComicEpisodes {
+    /// 已解锁的章节.
+    Unlocked,
+    /// 通过购买接口购买过的章节.
+    Bought,
+}
+
+/**
+ * 依次下载多部漫画中符合条件且未下载过的章节.
+ */
+async fn download_comics(
+    config: &config::Config,
+    options: &DownloadOptions,
+    comics: Vec<(u32, String)>,
+    selection: ComicEpisodes,
+    cancel_token: &CancellationToken,
+) {
+    for (manga_id, title) in comics {
+        if cancel_token.is_cancelled() {
+            break;
+        }
+
+        let dest_path = Path::new(&config.download_path).join(get_safe_filename(&title));
+        let manga_detail = match get_manga_detail(&manga_id, &config.cookie).await {
+            Ok(manga_detail) => manga_detail,
+            Err(e) => {
+                println!("{}", format!("{} 章节获取失败: {}", title, e).red());
+                continue;
+            }
+        };
+        let bought_ids = match selection {
+            ComicEpisodes::Unlocked => None,
+            ComicEpisodes::Bought => {
+                match list_bought_episode_ids(manga_id, &config.cookie).await {
+                    Ok(bought_ids) => Some(bought_ids),
+                    Err(e) => {
+                        println!("{}", format!("{} 已购章节获取失败: {}", title, e).red());
+                        continue;
+                    }
+                }
+            }
+        };
+        let episodes: Vec<Episode> = manga_detail
+            .ep_list
+            .into_iter()
+            .filter(|episode| match &bought_ids {
+                Some(bought_ids) => bought_ids.contains(&episode.id),
+                None => !episode.is_locked,
+            })
+            .filter(|episode| !dest_path.join(get_episode_filename(episode)).exists())
+            .collect();
+        if episodes.is_empty() {
+            println!("{}: 没有需要下载的章节", title);
+            continue;
+        }
+
+        println!("{}: 下载 {} 个章节", title.as_str().cyan(), episodes.len());
+        if let Err(e) = download_episodes_to(
+            manga_id,
+            episodes,
+            &config.cookie,
+            &dest_path,
+            options,
+            cancel_token,
+        )
+        .await
+        {
+            println!("{}", format!("{}: {}", title, e).red());
+        }
+    }
+}
+
+/**
+ * 下载书架中所有漫画里未下载过的已解锁章节.
+ */
+async fn download_bookshelf(
+    config: &config::Config,
+    options: &DownloadOptions,
+    cancel_token: &CancellationToken,
+) {
+    let comics = match list_favorites(&config.cookie).await {
+        Ok(comics) => comics,
+        Err(e) => {
+            println!("{}", format!("书架获取失败: {}", e).red());
+            return;
+        }
+    };
+    println!("书架中共有 {} 部漫画", comics.len().to_string().cyan());
+
+    let comics = comics
+        .into_iter()
+        .map(|comic| (comic.comic_id, comic.title))
+        .collect();
+    download_comics(
+        config,
+        options,
+        comics,
+        ComicEpisodes::Unlocked,
+        cancel_token,
+    )
+    .await;
+}
+
+/**
+ * 下载账号购买过的所有章节.
+ */
+async fn download_purchased(
+    config: &config::Config,
+    options: &DownloadOptions,
+    cancel_token: &CancellationToken,
+) {
+    let comics = match list_bought_comics(&config.cookie).await {
+        Ok(comics) => comics,
+        Err(e) => {
+            println!("{}", format!("已购漫画获取失败: {}", e).red());
+            return;
+        }
+    };
+    println!("共购买过 {} 部漫画的章节", comics.len().to_string().cyan());
+
+    let comics = comics
+        .into_iter()
+        .map(|comic| (comic.comic_id, comic.title))
+        .collect();
+    download_comics(config, options, comics, ComicEpisodes::Bought, cancel_token).await;
+}
+
+async fn run_download(args: Args) {
+    // 选择章节时终端处于 raw 模式, 需要在此之前监听退出信号以便恢复终端.
+    let cancel_token = spawn_shutdown_listener();
+    let config = load_user_config(
+        args.download_path.clone(),
+        get_proxy_options(&args),
+        args.max_rate,
+    )
+    .await;
+    let options = get_download_options(&args, &config);
+
+    if active_profile() != DEFAULT_PROFILE {
+        println!("当前账号: {}", active_profile().cyan());
+    }
+    println!("漫画下载目录: {}", config.download_path.clone().cyan());
+
+    if args.bookshelf || args.purchased {
+        if args.bookshelf {
+            download_bookshelf(&config, &options, &cancel_token).await;
+        } else {
+            download_purchased(&config, &options, &cancel_token).await;
+        }
+        save_changed_cookies(&config);
+        return;
+    }
+
+    let selected_manga = get_selected_manga(args.manga_name.clone()).await;
+
+    if selected_manga.is_none() {
+        return;
+    }
+
+    let selected_manga = selected_manga.unwrap();
+
     let manga_title = get_safe_filename(&selected_manga.title);
     let dest_path = Path::new(&config.download_path).join(&manga_title);
-    // 创建下载目录
-    create_desc_dir(&dest_path.to_str().unwrap());
 
-    // 获取下载失败提示信息
-    let failed_message = do_download_tasks(
+    let download_episodes = match get_resume_episodes(&dest_path, selected_manga.id) {
+        Some(episodes) => episodes,
+        None => match get_download_episodes(&selected_manga.id, &config.cookie, args.unlocked_only)
+            .await
+        {
+            Some(episodes) => episodes,
+            None => return,
+        },
+    };
+
+    let result = download_episodes_to(
         selected_manga.id,
         download_episodes,
         &config.cookie,
         &dest_path,
+        &options,
+        &cancel_token,
     )
     .await;
-
-    if failed_message.is_empty() {
-        println!("{}", "所有章节下载完成".green());
-        return;
-    }
-
-    for message in failed_message {
-        println!("{}", message.red());
+    save_changed_cookies(&config);
+    if let Err(e) = result {
+        println!("{}", e.to_string().red());
+        std::process::exit(1);
     }
 }
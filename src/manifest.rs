@@ -0,0 +1,83 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    apis::Episode,
+    utils::{get_part_path, sync_dir},
+};
+
+/**
+ * 章节压缩文件旁的校验清单, 记录每一页和整个压缩文件的 SHA-256.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EpisodeManifest {
+    pub manga_id: u32,
+    pub episode: Episode,
+    pub archive_sha256: String,
+    pub pages: Vec<PageManifest>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PageManifest {
+    /// 压缩文件内的文件名.
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// 是否为跨页.
+    #[serde(default)]
+    pub double_page: bool,
+}
+
+impl EpisodeManifest {
+    /**
+     * `[1]标题.zip` 对应 `[1]标题.manifest.json`.
+     */
+    pub fn path_for(archive_path: &Path) -> PathBuf {
+        archive_path.with_extension("manifest.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let content = serde_json::to_string_pretty(self)?;
+        let part_path = get_part_path(path);
+        std::fs::write(&part_path, content)?;
+        std::fs::rename(&part_path, path)?;
+        sync_dir(path);
+        Ok(())
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,733 @@
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use image::{
+    imageops::{self, ColorMap, FilterType},
+    DynamicImage, GenericImage, GrayImage, Luma, RgbImage,
+};
+
+use crate::{
+    imaging::Page,
+    profile::{AutoCrop, DeviceProfile},
+    transcode::{
+        encode_image, encode_processed, transcode_page, TranscodeFormat, TranscodeOptions,
+        TranscodeStats,
+    },
+};
+
+/// JPEG 支持的最大边长.
+const MAX_STITCH_HEIGHT: u32 = 65_000;
+
+/// 页面高宽比的允许范围.
+const PAGE_ASPECT_RANGE: std::ops::RangeInclusive<f32> = 0.25..=10.0;
+
+/**
+ * 条漫的处理方式, `aspect` 为目标页面的高宽比.
+ */
+#[derive(Debug, Clone, Copy)]
+pub enum StripMode {
+    /// 在空白处将过长的页面切分为高宽比为 `aspect` 的多页.
+    Split { aspect: f32 },
+    /// 将宽度相同、高度不足一页的连续短图拼接为一张长图.
+    Stitch { aspect: f32 },
+}
+
+/**
+ * 解析页面高宽比, 过小的高宽比会切出大量碎片.
+ */
+pub fn parse_page_aspect(aspect: &str) -> Result<f32, String> {
+    let aspect: f32 = aspect
+        .trim()
+        .parse()
+        .map_err(|_| format!("无效的高宽比: {}", aspect))?;
+    if !PAGE_ASPECT_RANGE.contains(&aspect) {
+        return Err(format!(
+            "高宽比必须在 {} 到 {} 之间",
+            PAGE_ASPECT_RANGE.start(),
+            PAGE_ASPECT_RANGE.end()
+        ));
+    }
+    Ok(aspect)
+}
+
+/**
+ * 跨页的处理方式.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SpreadMode {
+    /// 从中间切分为两页, 按从右到左的顺序排列
+    Split,
+    /// 顺时针旋转 90 度
+    Rotate,
+    /// 保留原跨页, 并在其后加入切分的两页
+    Both,
+}
+
+/**
+ * 写入压缩文件前对页面的处理.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptions {
+    pub strip: Option<StripMode>,
+    pub spread: Option<SpreadMode>,
+    pub auto_crop: Option<AutoCrop>,
+    pub profile: Option<DeviceProfile>,
+}
+
+impl ProcessOptions {
+    pub fn is_empty(&self) -> bool {
+        self.strip.is_none()
+            && self.spread.is_none()
+            && self.auto_crop.is_none()
+            && self.profile.is_none()
+    }
+
+    fn dithered(&self) -> bool {
+        self.profile
+            .as_ref()
+            .is_some_and(|profile| profile.grayscale && profile.dither)
+    }
+}
+
+/**
+ * 处理中的页面, 没有被修改过的页面保留原始数据, 避免重复编码.
+ */
+enum Item {
+    Original(Page),
+    /// 处理过的图片, 以及是否为跨页.
+    Image(DynamicImage, bool),
+}
+
+impl Item {
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Item::Original(page) => (page.width, page.height),
+            Item::Image(image, _) => (image.width(), image.height()),
+        }
+    }
+
+    fn is_double_page(&self) -> bool {
+        match self {
+            Item::Original(page) => page.double_page,
+            Item::Image(_, double_page) => *double_page,
+        }
+    }
+
+    fn decode(self) -> Result<DynamicImage, Item> {
+        match self {
+            Item::Image(image, _) => Ok(image),
+            Item::Original(page) => {
+                image::load_from_memory(&page.bytes).map_err(|_| Item::Original(page))
+            }
+        }
+    }
+}
+
+/**
+ * 墨水屏的等距灰阶.
+ */
+struct GrayLevels(u8);
+
+impl ColorMap for GrayLevels {
+    type Color = Luma<u8>;
+
+    fn index_of(&self, color: &Luma<u8>) -> usize {
+        let step = 255.0 / (self.0 - 1) as f32;
+        (color.0[0] as f32 / step).round() as usize
+    }
+
+    fn map_color(&self, color: &mut Luma<u8>) {
+        let step = 255.0 / (self.0 - 1) as f32;
+        color.0[0] = (self.index_of(color) as f32 * step).round() as u8;
+    }
+}
+
+fn apply_gamma(image: &mut GrayImage, gamma: f32) {
+    let table: Vec<u8> = (0..=255u32)
+        .map(|value| ((value as f32 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8)
+        .collect();
+    for pixel in image.pixels_mut() {
+        pixel.0[0] = table[pixel.0[0] as usize];
+    }
+}
+
+fn crop_margin(image: DynamicImage, margin: u32) -> DynamicImage {
+    if margin == 0 || image.width() <= margin * 2 || image.height() <= margin * 2 {
+        return image;
+    }
+    image.crop_imm(
+        margin,
+        margin,
+        image.width() - margin * 2,
+        image.height() - margin * 2,
+    )
+}
+
+/**
+ * 按设备配置处理一页: 裁边、缩放到屏幕内、灰度、伽马与对比度、抖动.
+ */
+fn apply_profile(image: DynamicImage, profile: &DeviceProfile) -> DynamicImage {
+    let mut image = crop_margin(image, profile.crop_margin);
+
+    if image.width() > profile.width || image.height() > profile.height {
+        image = image.resize(profile.width, profile.height, FilterType::Lanczos3);
+    }
+
+    if !profile.grayscale {
+        if profile.contrast != 0.0 {
+            image = image.adjust_contrast(profile.contrast);
+        }
+        return image;
+    }
+
+    let mut gray = image.to_luma8();
+    if profile.gamma != 1.0 && profile.gamma > 0.0 {
+        apply_gamma(&mut gray, profile.gamma);
+    }
+    if profile.contrast != 0.0 {
+        gray = imageops::contrast(&gray, profile.contrast);
+    }
+    if profile.dither {
+        imageops::dither(&mut gray, &GrayLevels(16));
+    }
+    DynamicImage::ImageLuma8(gray)
+}
+
+/**
+ * 每一行是否为颜色一致的空白行, 用于寻找分镜之间的间隙.
+ */
+fn blank_rows(image: &DynamicImage) -> Vec<bool> {
+    let gray = image.to_luma8();
+    gray.rows()
+        .map(|row| {
+            let (min, max) = row.fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+                (min.min(pixel.0[0]), max.max(pixel.0[0]))
+            });
+            max - min <= 16
+        })
+        .collect()
+}
+
+/**
+ * 在 `[min_y, ideal_y]` 内从下往上寻找空白行, 返回其所在空白区域的中间位置.
+ */
+fn find_cut(blank_rows: &[bool], min_y: u32, ideal_y: u32) -> Option<u32> {
+    let found = (min_y..=ideal_y).rev().find(|y| blank_rows[*y as usize])?;
+    let mut top = found;
+    while top > min_y && blank_rows[(top - 1) as usize] {
+        top -= 1;
+    }
+    let mut bottom = found;
+    while (bottom as usize) + 1 < blank_rows.len() && blank_rows[(bottom + 1) as usize] {
+        bottom += 1;
+    }
+    Some((top + bottom.min(ideal_y)) / 2)
+}
+
+/**
+ * 将过长的页面在空白处切分, 找不到空白时在目标高度处直接切开.
+ */
+fn split_tall(image: DynamicImage, aspect: f32) -> Vec<DynamicImage> {
+    let target_height = (image.width() as f32 * aspect) as u32;
+    // 略高于目标高度的页面不切分, 避免切出很窄的碎片.
+    if target_height == 0 || image.height() as f32 <= target_height as f32 * 1.2 {
+        return vec![image];
+    }
+
+    let blank_rows = blank_rows(&image);
+    let mut pages = vec![];
+    let mut y = 0;
+    while image.height() - y > target_height {
+        let ideal_y = y + target_height;
+        // 至少前进一行, 否则目标高度很小且顶部空白时会原地切出空页.
+        let min_y = y + (target_height * 3 / 4).max(1);
+        let cut_y = find_cut(&blank_rows, min_y, ideal_y).unwrap_or(ideal_y);
+        debug_assert!(cut_y > y);
+        let cut_y = if cut_y > y { cut_y } else { ideal_y };
+        pages.push(image.crop_imm(0, y, image.width(), cut_y - y));
+        y = cut_y;
+    }
+    pages.push(image.crop_imm(0, y, image.width(), image.height() - y));
+    pages
+}
+
+/**
+ * 将一组宽度相同的图片上下拼接.
+ */
+fn stitch(images: &[DynamicImage]) -> DynamicImage {
+    let width = images[0].width();
+    let height = images.iter().map(|image| image.height()).sum();
+    let grayscale = images.iter().all(|image| !image.color().has_color());
+    if grayscale {
+        let mut stitched = GrayImage::new(width, height);
+        let mut y = 0;
+        for image in images {
+            stitched
+                .copy_from(&image.to_luma8(), 0, y)
+                .expect("拼接图片失败");
+            y += image.height();
+        }
+        DynamicImage::ImageLuma8(stitched)
+    } else {
+        let mut stitched = RgbImage::new(width, height);
+        let mut y = 0;
+        for image in images {
+            stitched
+                .copy_from(&image.to_rgb8(), 0, y)
+                .expect("拼接图片失败");
+            y += image.height();
+        }
+        DynamicImage::ImageRgb8(stitched)
+    }
+}
+
+fn apply_strip_split(items: Vec<Item>, aspect: f32) -> Vec<Item> {
+    let mut result = vec![];
+    for item in items {
+        let (width, height) = item.size();
+        if (height as f32) <= width as f32 * aspect * 1.2 {
+            result.push(item);
+            continue;
+        }
+        match item.decode() {
+            Ok(image) => result.extend(
+                split_tall(image, aspect)
+                    .into_iter()
+                    .map(|image| Item::Image(image, false)),
+            ),
+            Err(item) => result.push(item),
+        }
+    }
+    result
+}
+
+fn apply_strip_stitch(items: Vec<Item>, aspect: f32) -> Vec<Item> {
+    let mut result = vec![];
+    // 待拼接的图片, 以及未修改过的原始页面.
+    let mut run: Vec<(DynamicImage, Option<Page>, bool)> = vec![];
+    let flush =
+        |run: &mut Vec<(DynamicImage, Option<Page>, bool)>, result: &mut Vec<Item>| match run.len()
+        {
+            0 => {}
+            1 => result.push(match run.remove(0) {
+                (_, Some(page), _) => Item::Original(page),
+                (image, None, double_page) => Item::Image(image, double_page),
+            }),
+            _ => {
+                let images: Vec<DynamicImage> = run.drain(..).map(|(image, _, _)| image).collect();
+                result.push(Item::Image(stitch(&images), false));
+            }
+        };
+
+    for item in items {
+        // 只拼接高度不足一页的短图, 普通页面保持原样.
+        let (width, height) = item.size();
+        if height as f32 >= width as f32 * aspect {
+            flush(&mut run, &mut result);
+            result.push(item);
+            continue;
+        }
+        let double_page = item.is_double_page();
+        let original = match &item {
+            Item::Original(page) => Some(page.clone()),
+            Item::Image(..) => None,
+        };
+        let image = match item.decode() {
+            Ok(image) => image,
+            Err(item) => {
+                flush(&mut run, &mut result);
+                result.push(item);
+                continue;
+            }
+        };
+        let run_height: u32 = run.iter().map(|(image, _, _)| image.height()).sum();
+        let fits = run
+            .first()
+            .is_some_and(|(first, _, _)| first.width() == image.width())
+            && run_height + image.height() <= MAX_STITCH_HEIGHT;
+        if !fits {
+            flush(&mut run, &mut result);
+        }
+        run.push((image, original, double_page));
+    }
+    flush(&mut run, &mut result);
+    result
+}
+
+/**
+ * 将跨页切分为右、左两页.
+ */
+fn split_spread(image: &DynamicImage) -> [DynamicImage; 2] {
+    let half = image.width() / 2;
+    let right = image.crop_imm(half, 0, image.width() - half, image.height());
+    let left = image.crop_imm(0, 0, half, image.height());
+    [right, left]
+}
+
+fn apply_spread(items: Vec<Item>, mode: SpreadMode) -> Vec<Item> {
+    let mut result = vec![];
+    for item in items {
+        let (width, height) = item.size();
+        if width <= height {
+            result.push(item);
+            continue;
+        }
+        // 横向的页面为跨页, 旋转后仍标记为跨页, 切分出的两页为单页.
+        let original = match &item {
+            Item::Original(page) => Some(Page {
+                double_page: true,
+                ..page.clone()
+            }),
+            Item::Image(..) => None,
+        };
+        let image = match item.decode() {
+            Ok(image) => image,
+            Err(item) => {
+                result.push(item);
+                continue;
+            }
+        };
+        let single_page = |image| Item::Image(image, false);
+        match mode {
+            SpreadMode::Split => result.extend(split_spread(&image).map(single_page)),
+            SpreadMode::Rotate => result.push(Item::Image(image.rotate90(), true)),
+            SpreadMode::Both => {
+                let halves = split_spread(&image);
+                result.push(match original {
+                    Some(page) => Item::Original(page),
+                    None => Item::Image(image, true),
+                });
+                result.extend(halves.map(single_page));
+            }
+        }
+    }
+    result
+}
+
+/**
+ * 第一个和最后一个满足条件的下标.
+ */
+fn content_range(is_content: impl Iterator<Item = bool>) -> Option<(u32, u32)> {
+    let mut first = None;
+    let mut last = None;
+    for (index, is_content) in is_content.enumerate() {
+        if is_content {
+            first.get_or_insert(index as u32);
+            last = Some(index as u32);
+        }
+    }
+    Some((first?, last?))
+}
+
+/**
+ * 计算去掉四周空白后的区域 `(x, y, width, height)`, 不需要或不应该裁剪时返回 `None`.
+ */
+fn auto_crop_bounds(image: &DynamicImage, auto_crop: &AutoCrop) -> Option<(u32, u32, u32, u32)> {
+    let gray = image.to_luma8();
+    let (width, height) = gray.dimensions();
+    let threshold = 255 - auto_crop.tolerance;
+    let is_ink = |pixel: &Luma<u8>| pixel.0[0] < threshold;
+
+    // 一行或一列中非空白像素超过 0.5% 才视为内容, 忽略扫描产生的噪点.
+    let row_min = (width / 200).max(1) as usize;
+    let col_min = (height / 200).max(1) as usize;
+    let (top, bottom) = content_range(
+        gray.rows()
+            .map(|row| row.filter(|pixel| is_ink(pixel)).count() >= row_min),
+    )?;
+    let (left, right) = content_range((0..width).map(|x| {
+        (0..height)
+            .filter(|y| is_ink(gray.get_pixel(x, *y)))
+            .count()
+            >= col_min
+    }))?;
+
+    // 保留 1% 的边距.
+    let pad_x = width / 100;
+    let pad_y = height / 100;
+    let left = left.saturating_sub(pad_x);
+    let top = top.saturating_sub(pad_y);
+    let right = (right + pad_x).min(width - 1);
+    let bottom = (bottom + pad_y).min(height - 1);
+    let crop_width = right - left + 1;
+    let crop_height = bottom - top + 1;
+
+    // 内容过少时裁剪可能会切掉浅色的内容, 跳过.
+    if (crop_width as f32) < width as f32 * auto_crop.min_content
+        || (crop_height as f32) < height as f32 * auto_crop.min_content
+    {
+        return None;
+    }
+    // 几乎没有白边时保留原图.
+    if crop_width + pad_x * 2 >= width && crop_height + pad_y * 2 >= height {
+        return None;
+    }
+
+    Some((left, top, crop_width, crop_height))
+}
+
+fn apply_auto_crop(items: Vec<Item>, auto_crop: &AutoCrop) -> Vec<Item> {
+    items
+        .into_iter()
+        .map(|item| {
+            let double_page = item.is_double_page();
+            let original = match &item {
+                Item::Original(page) => Some(page.clone()),
+                Item::Image(..) => None,
+            };
+            let image = match item.decode() {
+                Ok(image) => image,
+                Err(item) => return item,
+            };
+            match (auto_crop_bounds(&image, auto_crop), original) {
+                (Some((x, y, width, height)), _) => {
+                    Item::Image(image.crop_imm(x, y, width, height), double_page)
+                }
+                (None, Some(page)) => Item::Original(page),
+                (None, None) => Item::Image(image, double_page),
+            }
+        })
+        .collect()
+}
+
+fn apply_device_profile(items: Vec<Item>, profile: &DeviceProfile) -> Vec<Item> {
+    items
+        .into_iter()
+        .map(|item| {
+            let double_page = item.is_double_page();
+            match item.decode() {
+                Ok(image) => Item::Image(apply_profile(image, profile), double_page),
+                Err(item) => item,
+            }
+        })
+        .collect()
+}
+
+fn encode_item(
+    item: Item,
+    dithered: bool,
+    transcode: Option<(&TranscodeOptions, &TranscodeStats)>,
+) -> Result<Page, String> {
+    let (image, double_page) = match (item, transcode) {
+        (Item::Original(page), None) => return Ok(page),
+        (Item::Original(page), Some((options, stats))) => {
+            return Ok(transcode_page(page, options, stats))
+        }
+        (Item::Image(image, double_page), _) => (image, double_page),
+    };
+    let (bytes, format) = match transcode {
+        Some((options, stats)) => encode_processed(&image, options, stats)?,
+        // 抖动后的图片用 JPEG 保存会产生明显的噪点, 改用 PNG.
+        None if dithered => encode_image(&image, TranscodeFormat::Png, 90)?,
+        None => encode_image(&image, TranscodeFormat::Jpeg, 90)?,
+    };
+    Ok(Page {
+        bytes: Bytes::from(bytes),
+        format,
+        width: image.width(),
+        height: image.height(),
+        double_page,
+    })
+}
+
+/**
+ * 处理一个章节的所有页面, 依次为条漫切分或拼接、跨页处理、裁白边、设备处理,
+ * 无法解码的页面保留原图.
+ *
+ * 设置了 `transcode` 时最后转码, 处理过的页面只编码一次.
+ */
+pub fn process_pages(
+    pages: Vec<Page>,
+    options: &ProcessOptions,
+    transcode: Option<(&TranscodeOptions, &TranscodeStats)>,
+) -> Result<Vec<Page>, String> {
+    if options.is_empty() {
+        return Ok(match transcode {
+            Some((transcode, stats)) => pages
+                .into_iter()
+                .map(|page| transcode_page(page, transcode, stats))
+                .collect(),
+            None => pages,
+        });
+    }
+
+    let input_bytes: u64 = pages.iter().map(|page| page.bytes.len() as u64).sum();
+    let mut items: Vec<Item> = pages.into_iter().map(Item::Original).collect();
+    match options.strip {
+        Some(StripMode::Split { aspect }) => items = apply_strip_split(items, aspect),
+        Some(StripMode::Stitch { aspect }) => items = apply_strip_stitch(items, aspect),
+        None => {}
+    }
+    if let Some(spread) = options.spread {
+        items = apply_spread(items, spread);
+    }
+    if let Some(auto_crop) = &options.auto_crop {
+        items = apply_auto_crop(items, auto_crop);
+    }
+    if let Some(profile) = &options.profile {
+        items = apply_device_profile(items, profile);
+    }
+
+    // 处理过的页面以其来源的原图大小作为转码前的大小, 未修改的页面在转码时统计.
+    if let Some((_, stats)) = transcode {
+        let original_bytes: u64 = items
+            .iter()
+            .map(|item| match item {
+                Item::Original(page) => page.bytes.len() as u64,
+                Item::Image(..) => 0,
+            })
+            .sum();
+        stats.bytes_before.fetch_add(
+            input_bytes.saturating_sub(original_bytes),
+            Ordering::Relaxed,
+        );
+    }
+
+    let dithered = options.dithered();
+    items
+        .into_iter()
+        .map(|item| encode_item(item, dithered, transcode))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 白色背景, `ink_rows` 中的行画上黑白相间的内容.
+    fn strip(width: u32, height: u32, ink_rows: impl Fn(u32) -> bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            if ink_rows(y) && x % 2 == 0 {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        }))
+    }
+
+    fn heights(images: &[DynamicImage]) -> Vec<u32> {
+        images.iter().map(|image| image.height()).collect()
+    }
+
+    #[test]
+    fn find_cut_centers_in_gap() {
+        let mut rows = vec![false; 100];
+        rows[80..90].fill(true);
+        assert_eq!(find_cut(&rows, 75, 99), Some(84));
+        // 空白区域超过理想位置时只取到理想位置.
+        assert_eq!(find_cut(&rows, 75, 85), Some(82));
+    }
+
+    #[test]
+    fn find_cut_without_gap() {
+        let rows = vec![false; 100];
+        assert_eq!(find_cut(&rows, 75, 99), None);
+    }
+
+    #[test]
+    fn find_cut_stays_above_min_y() {
+        let rows = vec![true; 100];
+        let cut = find_cut(&rows, 10, 20).unwrap();
+        assert!((10..=20).contains(&cut));
+    }
+
+    #[test]
+    fn split_tall_cuts_at_gaps() {
+        // 每 100 行内容之间有 20 行空白.
+        let image = strip(100, 480, |y| y % 120 < 100);
+        let pages = split_tall(image, 1.5);
+        assert_eq!(pages.iter().map(|page| page.height()).sum::<u32>(), 480);
+        assert!(pages.len() > 1);
+        for page in &pages[..pages.len() - 1] {
+            assert!(page.height() <= 150);
+            assert!(page.height() >= 150 * 3 / 4);
+        }
+    }
+
+    #[test]
+    fn split_tall_without_gaps() {
+        let image = strip(100, 400, |_| true);
+        assert_eq!(heights(&split_tall(image, 1.5)), vec![150, 150, 100]);
+    }
+
+    #[test]
+    fn split_tall_keeps_short_pages() {
+        let image = strip(100, 170, |_| true);
+        assert_eq!(heights(&split_tall(image, 1.5)), vec![170]);
+    }
+
+    #[test]
+    fn split_tall_target_height_one() {
+        // 目标高度为 1 且整页空白时仍然逐行前进.
+        let image = strip(10, 20, |_| false);
+        let pages = split_tall(image, 0.1);
+        assert_eq!(heights(&pages), vec![1; 20]);
+    }
+
+    #[test]
+    fn stitch_only_short_strips() {
+        let items = vec![
+            Item::Image(strip(100, 40, |_| true), false),
+            Item::Image(strip(100, 60, |_| true), false),
+            Item::Image(strip(100, 300, |_| true), false),
+            Item::Image(strip(100, 50, |_| true), false),
+            Item::Image(strip(80, 50, |_| true), false),
+        ];
+        let sizes: Vec<(u32, u32)> = apply_strip_stitch(items, 1.5)
+            .iter()
+            .map(Item::size)
+            .collect();
+        assert_eq!(sizes, vec![(100, 100), (100, 300), (100, 50), (80, 50)]);
+    }
+
+    #[test]
+    fn rotated_spread_stays_double_page() {
+        let spread = || vec![Item::Image(strip(200, 100, |_| true), false)];
+        let rotated = apply_spread(spread(), SpreadMode::Rotate);
+        assert_eq!(rotated[0].size(), (100, 200));
+        assert!(rotated[0].is_double_page());
+
+        let split = apply_spread(spread(), SpreadMode::Split);
+        assert_eq!(split.len(), 2);
+        assert!(split.iter().all(|item| !item.is_double_page()));
+
+        let both = apply_spread(spread(), SpreadMode::Both);
+        let flags: Vec<bool> = both.iter().map(Item::is_double_page).collect();
+        assert_eq!(flags, vec![true, false, false]);
+    }
+
+    #[test]
+    fn transcode_stats_count_source_bytes() {
+        let image = strip(100, 400, |y| y % 120 < 100);
+        let (bytes, _) = encode_image(&image, TranscodeFormat::Png, 90).unwrap();
+        let page = crate::imaging::validate_page(Bytes::from(bytes), None, false).unwrap();
+        let source_bytes = page.bytes.len() as u64;
+        let options = ProcessOptions {
+            strip: Some(StripMode::Split { aspect: 1.5 }),
+            ..Default::default()
+        };
+        let transcode = TranscodeOptions {
+            format: TranscodeFormat::Jpeg,
+            quality: 80,
+            only_if_smaller: true,
+        };
+        let stats = TranscodeStats::default();
+
+        let pages = process_pages(vec![page], &options, Some((&transcode, &stats))).unwrap();
+        assert!(pages.len() > 1);
+        assert_eq!(stats.bytes_before.load(Ordering::Relaxed), source_bytes);
+        let after: u64 = pages.iter().map(|page| page.bytes.len() as u64).sum();
+        assert_eq!(stats.bytes_after.load(Ordering::Relaxed), after);
+    }
+
+    #[test]
+    fn page_aspect_range() {
+        assert_eq!(parse_page_aspect("1.5"), Ok(1.5));
+        assert!(parse_page_aspect("0").is_err());
+        assert!(parse_page_aspect("-1").is_err());
+        assert!(parse_page_aspect("0.01").is_err());
+        assert!(parse_page_aspect("NaN").is_err());
+        assert!(parse_page_aspect("inf").is_err());
+        assert!(parse_page_aspect("tall").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+
+/**
+ * 阅读设备配置, 下载后按设备屏幕处理每一页.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// 屏幕宽度, 单位为像素.
+    pub width: u32,
+    /// 屏幕高度, 单位为像素.
+    pub height: u32,
+    /// 是否转为灰度.
+    #[serde(default = "default_true")]
+    pub grayscale: bool,
+    /// 伽马值, 大于 1 时暗部更亮.
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    /// 对比度调整, 正数增强, 负数减弱.
+    #[serde(default)]
+    pub contrast: f32,
+    /// 是否抖动为墨水屏的 16 级灰度.
+    #[serde(default)]
+    pub dither: bool,
+    /// 缩放前从每条边裁掉的像素数.
+    #[serde(default)]
+    pub crop_margin: u32,
+    /// 自动裁掉页面四周的空白.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_crop: Option<AutoCrop>,
+}
+
+/**
+ * 自动裁白边设置.
+ */
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct AutoCrop {
+    /// 灰度值与纯白相差不超过该值的像素视为空白.
+    pub tolerance: u8,
+    /// 裁剪后的宽高至少保留原图的比例, 否则跳过该页.
+    pub min_content: f32,
+}
+
+impl Default for AutoCrop {
+    fn default() -> Self {
+        AutoCrop {
+            tolerance: 16,
+            min_content: 0.5,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_gamma() -> f32 {
+    1.0
+}
+
+impl DeviceProfile {
+    fn e_ink(name: &str, width: u32, height: u32) -> Self {
+        DeviceProfile {
+            name: name.to_string(),
+            width,
+            height,
+            grayscale: true,
+            gamma: 1.8,
+            contrast: 10.0,
+            dither: true,
+            crop_margin: 0,
+            auto_crop: Some(AutoCrop::default()),
+        }
+    }
+}
+
+/**
+ * 内置的阅读设备配置.
+ */
+pub fn builtin_profiles() -> Vec<DeviceProfile> {
+    vec![
+        DeviceProfile::e_ink("kindle-paperwhite", 1236, 1648),
+        DeviceProfile::e_ink("kindle-oasis", 1264, 1680),
+        DeviceProfile::e_ink("kindle-scribe", 1860, 2480),
+        DeviceProfile::e_ink("kobo-clara", 1072, 1448),
+        DeviceProfile::e_ink("kobo-libra2", 1264, 1680),
+        DeviceProfile::e_ink("kobo-sage", 1440, 1920),
+    ]
+}
+
+impl DeviceProfile {
+    /**
+     * 检查自定义配置, 宽高为 0 时会把每页缩放到 1 像素.
+     */
+    fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!(
+                "设备配置 {} 的 width 与 height 必须大于 0",
+                self.name
+            ));
+        }
+        if !self.gamma.is_finite() || self.gamma <= 0.0 {
+            return Err(format!("设备配置 {} 的 gamma 必须大于 0", self.name));
+        }
+        Ok(())
+    }
+}
+
+/**
+ * 按名称查找设备配置, 配置文件中的同名配置优先于内置配置.
+ */
+pub fn find_profile(
+    name: &str,
+    custom_profiles: &[DeviceProfile],
+) -> Result<DeviceProfile, String> {
+    let profile = custom_profiles
+        .iter()
+        .cloned()
+        .chain(builtin_profiles())
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| {
+            let names: Vec<String> = custom_profiles
+                .iter()
+                .cloned()
+                .chain(builtin_profiles())
+                .map(|profile| profile.name)
+                .collect();
+            format!("未知的设备配置: {}, 可选: {}", name, names.join(", "))
+        })?;
+    profile.validate()?;
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(width: u32, height: u32, gamma: f32) -> DeviceProfile {
+        DeviceProfile {
+            name: "custom".to_string(),
+            width,
+            height,
+            gamma,
+            ..find_profile("kobo-libra2", &[]).unwrap()
+        }
+    }
+
+    #[test]
+    fn builtin_profiles_are_valid() {
+        for profile in builtin_profiles() {
+            assert!(find_profile(&profile.name, &[]).is_ok(), "{}", profile.name);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_custom_profiles() {
+        for profile in [
+            custom(0, 1680, 1.0),
+            custom(1264, 0, 1.0),
+            custom(1264, 1680, 0.0),
+            custom(1264, 1680, -1.0),
+            custom(1264, 1680, f32::NAN),
+        ] {
+            let e = find_profile("custom", &[profile]).unwrap_err();
+            assert!(e.contains("custom"), "{}", e);
+        }
+        assert!(find_profile("custom", &[custom(1264, 1680, 1.2)]).is_ok());
+    }
+}
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::apis::Episode;
+use crate::utils::{get_part_path, sync_dir};
+
+const RESUME_FILE_NAME: &str = ".resume.json";
+
+/**
+ * 下载被取消时记录未完成的章节, 下次选择同一部漫画时可以继续下载.
+ */
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ResumeState {
+    pub manga_id: u32,
+    pub episodes: Vec<Episode>,
+}
+
+impl ResumeState {
+    pub fn load(dest_path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(dest_path.join(RESUME_FILE_NAME)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, dest_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let path = dest_path.join(RESUME_FILE_NAME);
+        let content = serde_json::to_string_pretty(self)?;
+        let part_path = get_part_path(&path);
+        std::fs::write(&part_path, content)?;
+        std::fs::rename(&part_path, &path)?;
+        sync_dir(&path);
+        Ok(())
+    }
+
+    pub fn remove(dest_path: &Path) {
+        let _ = std::fs::remove_file(dest_path.join(RESUME_FILE_NAME));
+    }
+}
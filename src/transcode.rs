@@ -0,0 +1,219 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    DynamicImage, ImageEncoder,
+};
+use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
+use zune_jpegxl::JxlSimpleEncoder;
+
+use crate::imaging::{Page, PageFormat};
+
+/**
+ * 转码的目标格式.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TranscodeFormat {
+    /// 有损 JPEG
+    Jpeg,
+    /// 无损 WebP
+    Webp,
+    /// 有损 AVIF, 编码较慢
+    Avif,
+    /// 无损 JPEG XL
+    Jxl,
+    /// 无损 PNG
+    Png,
+}
+
+impl TranscodeFormat {
+    /**
+     * 只支持无损编码的格式, 不使用转码质量.
+     */
+    pub fn is_lossless(&self) -> bool {
+        matches!(
+            self,
+            TranscodeFormat::Webp | TranscodeFormat::Jxl | TranscodeFormat::Png
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscodeOptions {
+    pub format: TranscodeFormat,
+    /// 有损格式的质量, 1-100.
+    pub quality: u8,
+    /// 只有转码后更小时才替换原图.
+    pub only_if_smaller: bool,
+}
+
+/**
+ * 所有章节共享的转码统计.
+ */
+#[derive(Debug, Default)]
+pub struct TranscodeStats {
+    pub transcoded: AtomicU64,
+    pub kept: AtomicU64,
+    pub bytes_before: AtomicU64,
+    pub bytes_after: AtomicU64,
+}
+
+impl TranscodeStats {
+    pub fn summary(&self) -> String {
+        let before = self.bytes_before.load(Ordering::Relaxed);
+        let after = self.bytes_after.load(Ordering::Relaxed);
+        let saved = before.saturating_sub(after);
+        let percent = if before > 0 {
+            saved as f64 * 100.0 / before as f64
+        } else {
+            0.0
+        };
+        format!(
+            "转码 {} 页, 保留原图 {} 页, {} -> {}, 节省 {} ({:.1}%)",
+            self.transcoded.load(Ordering::Relaxed),
+            self.kept.load(Ordering::Relaxed),
+            indicatif::HumanBytes(before),
+            indicatif::HumanBytes(after),
+            indicatif::HumanBytes(saved),
+            percent
+        )
+    }
+}
+
+fn encode_jxl(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let (data, color_space) = if image.color().has_color() {
+        (image.to_rgb8().into_raw(), ColorSpace::RGB)
+    } else {
+        (image.to_luma8().into_raw(), ColorSpace::Luma)
+    };
+    let options = EncoderOptions::new(
+        image.width() as usize,
+        image.height() as usize,
+        color_space,
+        BitDepth::Eight,
+    );
+    let mut buf = vec![];
+    JxlSimpleEncoder::new(&data, options)
+        .encode(&mut buf)
+        .map_err(|e| format!("{:?}", e))?;
+    Ok(buf)
+}
+
+/**
+ * 将图片编码为目标格式.
+ */
+pub fn encode_image(
+    image: &DynamicImage,
+    format: TranscodeFormat,
+    quality: u8,
+) -> Result<(Vec<u8>, PageFormat), String> {
+    let mut buf = vec![];
+    let page_format = match format {
+        TranscodeFormat::Jpeg => {
+            let encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+            if image.color().has_color() {
+                let image = image.to_rgb8();
+                encoder.write_image(
+                    &image,
+                    image.width(),
+                    image.height(),
+                    image::ExtendedColorType::Rgb8,
+                )
+            } else {
+                let image = image.to_luma8();
+                encoder.write_image(
+                    &image,
+                    image.width(),
+                    image.height(),
+                    image::ExtendedColorType::L8,
+                )
+            }
+            .map_err(|e| e.to_string())?;
+            PageFormat::Jpeg
+        }
+        TranscodeFormat::Webp => {
+            let image = image.to_rgba8();
+            WebPEncoder::new_lossless(&mut buf)
+                .write_image(
+                    &image,
+                    image.width(),
+                    image.height(),
+                    image::ExtendedColorType::Rgba8,
+                )
+                .map_err(|e| e.to_string())?;
+            PageFormat::Webp
+        }
+        TranscodeFormat::Avif => {
+            let image = image.to_rgba8();
+            AvifEncoder::new_with_speed_quality(&mut buf, 8, quality)
+                .write_image(
+                    &image,
+                    image.width(),
+                    image.height(),
+                    image::ExtendedColorType::Rgba8,
+                )
+                .map_err(|e| e.to_string())?;
+            PageFormat::Avif
+        }
+        TranscodeFormat::Jxl => {
+            buf = encode_jxl(image)?;
+            PageFormat::Jxl
+        }
+        TranscodeFormat::Png => {
+            image
+                .write_with_encoder(PngEncoder::new(&mut buf))
+                .map_err(|e| e.to_string())?;
+            PageFormat::Png
+        }
+    };
+    Ok((buf, page_format))
+}
+
+/**
+ * 转码一页图片, 无法解码的图片保持不变.
+ */
+pub fn transcode_page(page: Page, options: &TranscodeOptions, stats: &TranscodeStats) -> Page {
+    let before = page.bytes.len() as u64;
+    stats.bytes_before.fetch_add(before, Ordering::Relaxed);
+
+    let encoded = image::load_from_memory(&page.bytes)
+        .map_err(|e| e.to_string())
+        .and_then(|image| encode_image(&image, options.format, options.quality));
+    let page = match encoded {
+        Ok((bytes, format)) if !options.only_if_smaller || (bytes.len() as u64) < before => {
+            stats.transcoded.fetch_add(1, Ordering::Relaxed);
+            Page {
+                bytes: Bytes::from(bytes),
+                format,
+                ..page
+            }
+        }
+        _ => {
+            stats.kept.fetch_add(1, Ordering::Relaxed);
+            page
+        }
+    };
+
+    stats
+        .bytes_after
+        .fetch_add(page.bytes.len() as u64, Ordering::Relaxed);
+    page
+}
+
+/**
+ * 处理过的图片直接编码为目标格式, 避免先保存为 JPEG 再转码造成两次有损压缩.
+ */
+pub fn encode_processed(
+    image: &DynamicImage,
+    options: &TranscodeOptions,
+    stats: &TranscodeStats,
+) -> Result<(Vec<u8>, PageFormat), String> {
+    let encoded = encode_image(image, options.format, options.quality)?;
+    // 转码前的大小由 `process_pages` 按下载的原图统计.
+    stats.transcoded.fetch_add(1, Ordering::Relaxed);
+    stats
+        .bytes_after
+        .fetch_add(encoded.0.len() as u64, Ordering::Relaxed);
+    Ok(encoded)
+}
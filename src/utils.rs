@@ -1,8 +1,10 @@
 use rand::Rng;
 use reqwest::header::{self, HeaderMap};
 
+use crate::cookie::CookieJar;
+
 fn get_random_ua() -> String {
-    let uas = vec![
+    let uas = [
         // Chrome
         "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Safari/537.36",
         // Firefox
@@ -24,16 +26,25 @@ fn get_random_ua() -> String {
     uas[index].to_string()
 }
 
-pub fn get_reqwest_headers(referer_url: &str, cookie: &str) -> HeaderMap {
+pub fn get_reqwest_headers(referer_url: &str, cookie: &CookieJar) -> HeaderMap {
     let ua = get_random_ua();
     let mut headers = HeaderMap::new();
-    headers.append(header::ORIGIN, "https://manga.bilibili.com".parse().unwrap());
+    headers.append(
+        header::ORIGIN,
+        "https://manga.bilibili.com".parse().unwrap(),
+    );
     headers.append(header::REFERER, referer_url.parse().unwrap());
     headers.append(header::USER_AGENT, ua.parse().unwrap());
 
-    let cookie = format!("SESSDATA={}", cookie);
-    headers.append(header::COOKIE, cookie.parse().unwrap());
-    headers.append(header::CONTENT_TYPE, "application/json;charset=UTF-8".parse().unwrap());
+    if !cookie.is_empty() {
+        if let Ok(cookie) = cookie.header_value().parse() {
+            headers.append(header::COOKIE, cookie);
+        }
+    }
+    headers.append(
+        header::CONTENT_TYPE,
+        "application/json;charset=UTF-8".parse().unwrap(),
+    );
 
     headers
 }
@@ -53,3 +64,178 @@ pub fn get_safe_filename(filename: &str) -> String {
     let reg = regex::Regex::new("[\\/:*?\"<>|\\s]").unwrap();
     reg.replace_all(filename, "").to_string()
 }
+
+/**
+ * 解析带宽限制, 支持 `2MiB/s`、`500KB/s`、`1048576` 等写法, 返回每秒字节数.
+ */
+pub fn parse_rate(rate: &str) -> Result<u64, String> {
+    let rate = rate.trim();
+    let rate = rate
+        .strip_suffix("/s")
+        .or_else(|| rate.strip_suffix("/S"))
+        .unwrap_or(rate)
+        .trim();
+    let split_index = rate
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(rate.len());
+    let (number, unit) = rate.split_at(split_index);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("无效的速率: {}", rate))?;
+    let multiplier: f64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1000.0,
+        "m" | "mb" => 1000.0 * 1000.0,
+        "g" | "gb" => 1000.0 * 1000.0 * 1000.0,
+        "ki" | "kib" => 1024.0,
+        "mi" | "mib" => 1024.0 * 1024.0,
+        "gi" | "gib" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("无效的速率单位: {}", unit)),
+    };
+    let bytes_per_sec = (number * multiplier) as u64;
+    if bytes_per_sec == 0 {
+        return Err("速率必须大于 0".to_string());
+    }
+    Ok(bytes_per_sec)
+}
+
+/**
+ * 所有下载任务共享的限速器.
+ *
+ * 每次请求先在锁内预约下一段可用的发送时间, 再在锁外等待, 任务按到达顺序依次获得带宽.
+ */
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    next_free: tokio::sync::Mutex<tokio::time::Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            bytes_per_sec,
+            next_free: tokio::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    pub async fn acquire(&self, bytes: usize) {
+        let cost = std::time::Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let start = {
+            let mut next_free = self.next_free.lock().await;
+            let now = tokio::time::Instant::now();
+            let start = (*next_free).max(now);
+            *next_free = start + cost;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+/**
+ * 等待 Ctrl-C 或 SIGTERM.
+ */
+pub async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate()).expect("监听 SIGTERM 失败");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+const PART_EXTENSION: &str = "part";
+
+/**
+ * 文件写入完成前使用的临时路径, 如 `[1]标题.zip.part`.
+ */
+pub fn get_part_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".");
+    part_path.push(PART_EXTENSION);
+    std::path::PathBuf::from(part_path)
+}
+
+/**
+ * 重命名后同步所在目录, 确保重命名本身也写入磁盘.
+ */
+pub fn sync_dir(path: &std::path::Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = std::fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/**
+ * 清理上次中断时残留的 `.part` 临时文件, 返回清理的文件数.
+ */
+pub fn remove_stale_part_files(dir: &std::path::Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == PART_EXTENSION))
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    #[test]
+    fn parse_rate_plain_bytes() {
+        assert_eq!(parse_rate("1048576"), Ok(1048576));
+        assert_eq!(parse_rate("512B/s"), Ok(512));
+    }
+
+    #[test]
+    fn parse_rate_units() {
+        assert_eq!(parse_rate("500KB/s"), Ok(500_000));
+        assert_eq!(parse_rate("2MiB/s"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_rate("1.5 MB/S"), Ok(1_500_000));
+        assert_eq!(parse_rate("1gib"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_rate(" 64k "), Ok(64_000));
+    }
+
+    #[test]
+    fn parse_rate_rejects_invalid() {
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("10XB/s").is_err());
+        assert!(parse_rate("0").is_err());
+        // 不足 1 字节每秒.
+        assert!(parse_rate("0.5").is_err());
+    }
+
+    #[test]
+    fn part_path_appends_extension() {
+        assert_eq!(
+            get_part_path(Path::new("/tmp/漫画/[1]第一话.zip")),
+            PathBuf::from("/tmp/漫画/[1]第一话.zip.part")
+        );
+        // 不替换已有的扩展名.
+        assert_eq!(
+            get_part_path(Path::new("[preview][2]b.zip")),
+            PathBuf::from("[preview][2]b.zip.part")
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::comicinfo::COMIC_INFO_FILE_NAME;
+use crate::manifest::{sha256_file, sha256_hex, EpisodeManifest};
+
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/**
+ * 校验失败的章节.
+ */
+#[derive(Debug)]
+pub struct BrokenEpisode {
+    pub archive_path: PathBuf,
+    pub manifest: EpisodeManifest,
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// 校验通过的压缩文件数.
+    pub ok: usize,
+    pub broken: Vec<BrokenEpisode>,
+    /// 没有校验清单, 无法校验的压缩文件.
+    pub unverified: Vec<PathBuf>,
+}
+
+fn collect_files(dir: &Path, manifests: &mut Vec<PathBuf>, archives: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, manifests, archives);
+            continue;
+        }
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.ends_with(MANIFEST_SUFFIX) {
+            manifests.push(path);
+        } else if file_name.ends_with(".zip") {
+            archives.push(path);
+        }
+    }
+}
+
+fn archive_path_for(manifest_path: &Path) -> PathBuf {
+    let file_name = manifest_path.file_name().unwrap().to_string_lossy();
+    let stem = file_name.strip_suffix(MANIFEST_SUFFIX).unwrap();
+    manifest_path.with_file_name(format!("{}.zip", stem))
+}
+
+/**
+ * 逐页比较压缩文件与清单, 返回缺失、损坏和多余的页面.
+ */
+fn compare_pages(
+    archive_path: &Path,
+    manifest: &EpisodeManifest,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(archive_path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    let mut archive_pages = HashMap::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let mut bytes = vec![];
+        let name = entry.name().to_string();
+        if name == COMIC_INFO_FILE_NAME {
+            continue;
+        }
+        match entry.read_to_end(&mut bytes) {
+            Ok(_) => archive_pages.insert(name, Some(sha256_hex(&bytes))),
+            Err(_) => archive_pages.insert(name, None),
+        };
+    }
+
+    let mut problems = vec![];
+    for page in &manifest.pages {
+        match archive_pages.remove(&page.name) {
+            None => problems.push(format!("缺少页面 {}", page.name)),
+            Some(Some(sha256)) if sha256 == page.sha256 => {}
+            Some(_) => problems.push(format!("页面损坏 {}", page.name)),
+        }
+    }
+    let mut extra_pages: Vec<String> = archive_pages.into_keys().collect();
+    extra_pages.sort();
+    for name in extra_pages {
+        problems.push(format!("多余页面 {}", name));
+    }
+
+    Ok(problems)
+}
+
+fn verify_manifest(manifest_path: &Path) -> Result<Option<BrokenEpisode>, String> {
+    let manifest = EpisodeManifest::load(manifest_path)
+        .map_err(|e| format!("{} 清单读取失败: {}", manifest_path.display(), e))?;
+    let archive_path = archive_path_for(manifest_path);
+
+    let problems = if !archive_path.exists() {
+        vec!["压缩文件不存在".to_string()]
+    } else {
+        match sha256_file(&archive_path) {
+            Ok(sha256) if sha256 == manifest.archive_sha256 => return Ok(None),
+            Ok(_) => match compare_pages(&archive_path, &manifest) {
+                Ok(problems) if problems.is_empty() => {
+                    vec!["压缩文件校验值不一致".to_string()]
+                }
+                Ok(problems) => problems,
+                Err(e) => vec![format!("压缩文件无法读取: {}", e)],
+            },
+            Err(e) => vec![format!("压缩文件无法读取: {}", e)],
+        }
+    };
+
+    Ok(Some(BrokenEpisode {
+        archive_path,
+        manifest,
+        problems,
+    }))
+}
+
+/**
+ * 遍历目录, 根据校验清单重新计算每个压缩文件的 SHA-256.
+ */
+pub fn verify_dir(root: &Path) -> VerifyReport {
+    let mut manifests = vec![];
+    let mut archives = vec![];
+    collect_files(root, &mut manifests, &mut archives);
+    manifests.sort();
+    archives.sort();
+
+    let mut report = VerifyReport::default();
+    let pb = ProgressBar::new(manifests.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .expect("Failed to set bar template")
+            .progress_chars("#>-"),
+    );
+    pb.set_message("校验中");
+
+    for manifest_path in &manifests {
+        match verify_manifest(manifest_path) {
+            Ok(None) => report.ok += 1,
+            Ok(Some(broken)) => report.broken.push(broken),
+            Err(e) => pb.println(e),
+        }
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    report.unverified = archives
+        .into_iter()
+        .filter(|archive_path| !EpisodeManifest::path_for(archive_path).exists())
+        .collect();
+
+    report
+}